@@ -0,0 +1,263 @@
+//! The coordinate systems we juggle, and the conversions between them:
+//!  - hex-local: what the warapi gives us. `x`/`y` from 0 to 1 across the bounding box of
+//!    the hex's terrain image, top left being 0,0.
+//!  - world: metres, with the center of the grid's 0,0 hex (Deadlands) at the origin,
+//!    x going east and y going south, like on the map images.
+//!  - canvas: pixels of the svg we draw.
+//!
+//! The hexes are flat-topped and laid out on an axial grid, where the first grid coordinate
+//! goes down-right and the second one straight down.
+
+pub const COS_30: f32 = 0.866_025_4;
+pub const SIN_30: f32 = 0.5;
+
+/// Corner to corner width of a hex. The usual number the community tools go with.
+pub const HEX_WIDTH_M: f32 = 2184.0;
+/// Edge to edge height of a hex. Hexes are regular, so this follows from the width.
+pub const HEX_HEIGHT_M: f32 = HEX_WIDTH_M * COS_30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldPos {
+    pub x: f32,
+    pub y: f32,
+}
+impl WorldPos {
+    pub fn distance_to(&self, other: &WorldPos) -> f32 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+    }
+
+    /// 8-point compass direction from here to there. North is up (negative y).
+    pub fn direction_to(&self, other: &WorldPos) -> &'static str {
+        const DIRECTIONS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+        // clockwise from north
+        let angle = (other.x - self.x).atan2(self.y - other.y).to_degrees();
+        DIRECTIONS[((angle / 45.0).round() as i32).rem_euclid(8) as usize]
+    }
+}
+
+/// Corners of a flat-topped hexagon with the given (corner to corner) radius,
+/// relative to the top left of its bounding box. In whatever units the radius is in.
+/// Everything that needs to know where exactly a hex ends should go through this.
+pub fn hex_polygon_points(radius_long: f32) -> Vec<(f32, f32)> {
+    let points = vec![
+        (-radius_long, 0.0),
+        (-radius_long * SIN_30, radius_long * COS_30),
+        (radius_long * SIN_30, radius_long * COS_30),
+        (radius_long, 0.0),
+        (radius_long * SIN_30, -radius_long * COS_30),
+        (-radius_long * SIN_30, -radius_long * COS_30),
+    ];
+    points
+        .into_iter()
+        .map(|(x, y)| (x + radius_long, y + radius_long * COS_30))
+        .collect()
+}
+
+/// Center of the hex at the given grid coordinate.
+pub fn hex_center(grid_coord: (i32, i32)) -> WorldPos {
+    let (q, r) = (grid_coord.0 as f32, grid_coord.1 as f32);
+    WorldPos {
+        x: q * COS_30 * HEX_HEIGHT_M,
+        y: (q * SIN_30 + r) * HEX_HEIGHT_M,
+    }
+}
+
+/// Top left of the hex's bounding box.
+pub fn hex_top_left(grid_coord: (i32, i32)) -> WorldPos {
+    let center = hex_center(grid_coord);
+    WorldPos {
+        x: center.x - HEX_WIDTH_M / 2.0,
+        y: center.y - HEX_HEIGHT_M / 2.0,
+    }
+}
+
+pub fn hex_local_to_world(grid_coord: (i32, i32), x: f32, y: f32) -> WorldPos {
+    let top_left = hex_top_left(grid_coord);
+    WorldPos {
+        x: top_left.x + x * HEX_WIDTH_M,
+        y: top_left.y + y * HEX_HEIGHT_M,
+    }
+}
+
+/// The hex's outline, in world coordinates. Same corners, in the same order, as
+/// `hex_polygon_points`.
+pub fn hex_corners(grid_coord: (i32, i32)) -> Vec<WorldPos> {
+    // All the corners of the grid sit on a lattice of quarter hex widths and half hex heights.
+    // Counting on that lattice in integers means that neighbouring hexes get exactly the same
+    // floats for the corners they share, which adding up offsets wouldn't.
+    let (q, r) = grid_coord;
+    let (center_x, center_y) = (3 * q, q + 2 * r);
+    [(-2, 0), (-1, 1), (1, 1), (2, 0), (1, -1), (-1, -1)]
+        .into_iter()
+        .map(|(dx, dy)| WorldPos {
+            x: (center_x + dx) as f32 * (HEX_WIDTH_M / 4.0),
+            y: (center_y + dy) as f32 * (HEX_HEIGHT_M / 2.0),
+        })
+        .collect()
+}
+
+/// The in-game map grid, over the bounding box of each hex: columns A to Q, rows 1 to 15,
+/// and every cell split into a 3x3 keypad, numbered like a numpad (7 8 9 on top).
+pub const GRID_COLUMNS: u32 = 17;
+pub const GRID_ROWS: u32 = 15;
+
+/// The grid reference players use, like "G7k3", for a hex-local position.
+pub fn grid_reference(x: f32, y: f32) -> String {
+    // things right on the edge (or a bit over) still belong to the outermost cells
+    let cell = |v: f32, cells: u32| {
+        let v = v.clamp(0.0, 1.0) * cells as f32;
+        let cell = (v.floor() as u32).min(cells - 1);
+        let third = (((v - cell as f32) * 3.0).floor() as u32).min(2);
+        (cell, third)
+    };
+    let (column, sub_column) = cell(x, GRID_COLUMNS);
+    let (row, sub_row) = cell(y, GRID_ROWS);
+    format!(
+        "{}{}k{}",
+        (b'A' + column as u8) as char,
+        row + 1,
+        (2 - sub_row) * 3 + sub_column + 1
+    )
+}
+
+/// Maps the world onto the canvas. Just a scale and an offset, as both are north-up.
+#[derive(Clone, Copy, Debug)]
+pub struct CanvasTransform {
+    pub px_per_metre: f32,
+    /// where the world origin ends up on the canvas
+    pub origin_px: (f32, f32),
+}
+impl CanvasTransform {
+    /// Scaled so that a hex is `hex_width_px` wide (corner to corner). The 0,0 hex sits
+    /// four columns to the right of the canvas origin, which keeps most of the world in
+    /// positive coordinates (the final image gets shifted to fit anyway).
+    pub fn for_hex_width(hex_width_px: f32) -> Self {
+        let px_per_metre = hex_width_px / HEX_WIDTH_M;
+        let hex_height_px = HEX_HEIGHT_M * px_per_metre;
+        Self {
+            px_per_metre,
+            origin_px: (
+                COS_30 * 4.0 * hex_height_px + hex_width_px / 2.0,
+                hex_height_px / 2.0,
+            ),
+        }
+    }
+
+    pub fn to_canvas(self, pos: WorldPos) -> (f32, f32) {
+        (
+            self.origin_px.0 + pos.x * self.px_per_metre,
+            self.origin_px.1 + pos.y * self.px_per_metre,
+        )
+    }
+
+    /// The same transform, for a canvas that got shifted by the given amount.
+    pub fn shifted(&self, by: (f32, f32)) -> Self {
+        Self {
+            px_per_metre: self.px_per_metre,
+            origin_px: (self.origin_px.0 + by.0, self.origin_px.1 + by.1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f32, f32), b: (f32, f32), tolerance: f32) {
+        assert!(
+            (a.0 - b.0).abs() <= tolerance && (a.1 - b.1).abs() <= tolerance,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn deadlands_is_the_origin() {
+        assert_eq!(hex_center((0, 0)), WorldPos { x: 0.0, y: 0.0 });
+    }
+
+    #[test]
+    fn hex_local_spans_the_bounding_box() {
+        for grid_coord in [(0, 0), (3, -2), (-3, 1), (-1, 4)] {
+            let top_left = hex_top_left(grid_coord);
+            let center = hex_center(grid_coord);
+            let cases = [
+                ((0.0, 0.0), (top_left.x, top_left.y)),
+                ((0.5, 0.5), (center.x, center.y)),
+                (
+                    (1.0, 1.0),
+                    (top_left.x + HEX_WIDTH_M, top_left.y + HEX_HEIGHT_M),
+                ),
+            ];
+            for ((x, y), expected) in cases {
+                let world = hex_local_to_world(grid_coord, x, y);
+                assert_close((world.x, world.y), expected, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn canvas_scale_and_shift() {
+        let layout = CanvasTransform::for_hex_width(1024.0);
+        let shifted = layout.shifted((-123.5, 56.5));
+        for grid_coord in [(0, 0), (2, -3), (-4, 2)] {
+            let corners = hex_corners(grid_coord)
+                .into_iter()
+                .map(|c| layout.to_canvas(c))
+                .collect::<Vec<_>>();
+            // west to east corner is one hex width
+            assert_close(
+                (corners[3].0 - corners[0].0, corners[3].1),
+                (1024.0, corners[0].1),
+                1e-2,
+            );
+            let moved = shifted.to_canvas(hex_corners(grid_coord)[0]);
+            assert_close(moved, (corners[0].0 - 123.5, corners[0].1 + 56.5), 1e-2);
+        }
+    }
+
+    #[test]
+    fn neighbours_share_corners() {
+        // each neighbour, and which of our corners are also theirs (and which of theirs)
+        // going around `hex_polygon_points` from the west corner, counter-clockwise on screen
+        let cases = [
+            ((0, -1), [(4, 2), (5, 1)]),
+            ((1, -1), [(3, 1), (4, 0)]),
+            ((1, 0), [(2, 0), (3, 5)]),
+            ((0, 1), [(1, 5), (2, 4)]),
+            ((-1, 1), [(0, 4), (1, 3)]),
+            ((-1, 0), [(0, 2), (5, 3)]),
+        ];
+        for grid_coord in [(0, 0), (2, -3), (-4, 2)] {
+            let ours = hex_corners(grid_coord);
+            for ((dq, dr), shared) in cases {
+                let theirs = hex_corners((grid_coord.0 + dq, grid_coord.1 + dr));
+                for (i, j) in shared {
+                    assert_eq!(ours[i], theirs[j], "{:?} + {:?}", grid_coord, (dq, dr));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_references() {
+        let cases = [
+            ((0.0, 0.0), "A1k7"),
+            ((1.0, 1.0), "Q15k3"),
+            // a bit off the edge still counts as the edge
+            ((-0.1, 1.2), "A15k1"),
+            ((0.5, 0.5), "I8k5"),
+            // the keypad, within the top left cell
+            ((0.1 / 17.0, 0.1 / 15.0), "A1k7"),
+            ((1.5 / 17.0 / 3.0, 0.1 / 15.0), "A1k8"),
+            ((2.9 / 17.0 / 3.0, 0.1 / 15.0), "A1k9"),
+            ((0.1 / 17.0, 1.5 / 15.0 / 3.0), "A1k4"),
+            ((2.9 / 17.0 / 3.0, 2.9 / 15.0 / 3.0), "A1k3"),
+            ((1.1 / 17.0, 0.0), "B1k7"),
+        ];
+        for ((x, y), expected) in cases {
+            assert_eq!(grid_reference(x, y), expected, "{}, {}", x, y);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Making overlapping icons within a hex readable again.
+//! Mines and fields like to sit right on top of each other, and just stacking them
+//! (even sorted top-to-bottom) makes dense resource clusters into an unreadable blob.
+
+/// An icon about to be placed, all in hex terrain pixels.
+#[derive(Clone)]
+pub struct PlacedIcon {
+    pub icon_id: String,
+    /// center of the icon
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// which map item it is, for whoever needs to know more about it later.
+    /// Merged icons keep the first one's.
+    pub item: usize,
+}
+impl PlacedIcon {
+    fn overlaps(&self, other: &PlacedIcon) -> bool {
+        (self.x - other.x).abs() < (self.width + other.width) / 2.0
+            && (self.y - other.y).abs() < (self.height + other.height) / 2.0
+    }
+}
+
+pub struct Placement {
+    pub icon: PlacedIcon,
+    /// how many icons got merged into this one
+    pub count: usize,
+    /// where the icon would have been had it not been moved, if it was moved
+    pub anchor: Option<(f32, f32)>,
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeclutterMode {
+    /// stack them as they are
+    #[default]
+    None,
+    /// push overlapping icons apart, with a leader line back to where they actually are
+    Nudge,
+    /// replace overlapping icons of the same type with a single one with a count badge
+    Merge,
+}
+
+pub fn declutter(icons: Vec<PlacedIcon>, mode: DeclutterMode) -> Vec<Placement> {
+    match mode {
+        DeclutterMode::None => icons
+            .into_iter()
+            .map(|icon| Placement {
+                icon,
+                count: 1,
+                anchor: None,
+            })
+            .collect(),
+        DeclutterMode::Nudge => nudge(icons),
+        DeclutterMode::Merge => merge(icons),
+    }
+}
+
+fn nudge(mut icons: Vec<PlacedIcon>) -> Vec<Placement> {
+    let anchors = icons.iter().map(|i| (i.x, i.y)).collect::<Vec<_>>();
+    // Push each overlapping pair apart along whichever axis gets them out of each other
+    // the quickest. That may shove them into some third icon, hence the iterations.
+    // Clusters are a handful of icons at most, so quadratic is fine.
+    for _ in 0..100 {
+        let mut moved = false;
+        for a in 0..icons.len() {
+            for b in a + 1..icons.len() {
+                if !icons[a].overlaps(&icons[b]) {
+                    continue;
+                }
+                moved = true;
+                let (dx, dy) = (icons[b].x - icons[a].x, icons[b].y - icons[a].y);
+                let overlap_x = (icons[a].width + icons[b].width) / 2.0 - dx.abs();
+                let overlap_y = (icons[a].height + icons[b].height) / 2.0 - dy.abs();
+                // a tiny bit extra so that they end up touching rather than still overlapping
+                // due to float rounding
+                if overlap_x < overlap_y {
+                    let push = (overlap_x / 2.0 + 0.01) * if dx < 0.0 { -1.0 } else { 1.0 };
+                    icons[a].x -= push;
+                    icons[b].x += push;
+                } else {
+                    let push = (overlap_y / 2.0 + 0.01) * if dy < 0.0 { -1.0 } else { 1.0 };
+                    icons[a].y -= push;
+                    icons[b].y += push;
+                }
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+    icons
+        .into_iter()
+        .zip(anchors)
+        .map(|(icon, (ax, ay))| {
+            // sub-pixel moves are not worth a line
+            let moved = (icon.x - ax).abs() > 0.5 || (icon.y - ay).abs() > 0.5;
+            Placement {
+                icon,
+                count: 1,
+                anchor: moved.then_some((ax, ay)),
+            }
+        })
+        .collect()
+}
+
+fn merge(icons: Vec<PlacedIcon>) -> Vec<Placement> {
+    // group together everything of the same type that overlaps, even transitively
+    let mut group = (0..icons.len()).collect::<Vec<_>>();
+    fn root(group: &mut [usize], mut i: usize) -> usize {
+        while group[i] != i {
+            group[i] = group[group[i]];
+            i = group[i];
+        }
+        i
+    }
+    for a in 0..icons.len() {
+        for b in a + 1..icons.len() {
+            if icons[a].icon_id == icons[b].icon_id && icons[a].overlaps(&icons[b]) {
+                let (ra, rb) = (root(&mut group, a), root(&mut group, b));
+                group[rb] = ra;
+            }
+        }
+    }
+
+    // keep the original order (of the first icon in each group), it matters for layering
+    let mut out: Vec<Placement> = vec![];
+    let mut out_index = std::collections::HashMap::new();
+    for (i, icon) in icons.into_iter().enumerate() {
+        let r = root(&mut group, i);
+        match out_index.get(&r) {
+            None => {
+                out_index.insert(r, out.len());
+                out.push(Placement {
+                    icon,
+                    count: 1,
+                    anchor: None,
+                });
+            }
+            Some(&j) => {
+                // running average, so the merged one ends up at the centroid
+                let p = &mut out[j];
+                p.count += 1;
+                p.icon.x += (icon.x - p.icon.x) / p.count as f32;
+                p.icon.y += (icon.y - p.icon.y) / p.count as f32;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icon(icon_id: &str, x: f32, y: f32, item: usize) -> PlacedIcon {
+        PlacedIcon {
+            icon_id: icon_id.to_owned(),
+            x,
+            y,
+            width: 10.0,
+            height: 10.0,
+            item,
+        }
+    }
+
+    #[test]
+    fn merge_groups() {
+        // icons, and the (count, first item) of what comes out, in order
+        let cases = [
+            // stacked on one point
+            (
+                vec![icon("a", 0.0, 0.0, 0), icon("a", 0.0, 0.0, 1)],
+                vec![(2, 0)],
+            ),
+            // touching isn't overlapping
+            (
+                vec![icon("a", 0.0, 0.0, 0), icon("a", 10.0, 0.0, 1)],
+                vec![(1, 0), (1, 1)],
+            ),
+            // different types stay apart, even on top of each other
+            (
+                vec![icon("a", 0.0, 0.0, 0), icon("b", 0.0, 0.0, 1)],
+                vec![(1, 0), (1, 1)],
+            ),
+            // a chain, where the ends don't overlap but both overlap the middle
+            (
+                vec![
+                    icon("a", 0.0, 0.0, 0),
+                    icon("b", 50.0, 50.0, 1),
+                    icon("a", 16.0, 0.0, 2),
+                    icon("a", 8.0, 0.0, 3),
+                ],
+                vec![(3, 0), (1, 1)],
+            ),
+        ];
+        for (icons, expected) in cases {
+            let got = merge(icons)
+                .iter()
+                .map(|p| (p.count, p.icon.item))
+                .collect::<Vec<_>>();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn merge_ends_up_at_the_centroid() {
+        let merged = merge(vec![
+            icon("a", 0.0, 0.0, 0),
+            icon("a", 6.0, 0.0, 1),
+            icon("a", 3.0, 9.0, 2),
+        ]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!((merged[0].icon.x, merged[0].icon.y), (3.0, 3.0));
+    }
+
+    #[test]
+    fn nudge_separates() {
+        let cases: &[&[PlacedIcon]] = &[
+            &[icon("a", 0.0, 0.0, 0), icon("b", 0.0, 0.0, 1)],
+            &[icon("a", 0.0, 0.0, 0), icon("a", 3.0, 1.0, 1)],
+            &[
+                icon("a", 0.0, 0.0, 0),
+                icon("b", 2.0, 0.0, 1),
+                icon("c", 4.0, 0.0, 2),
+                icon("d", 2.0, 2.0, 3),
+            ],
+        ];
+        for icons in cases {
+            let placed = nudge(icons.to_vec());
+            assert_eq!(placed.len(), icons.len());
+            for (i, a) in placed.iter().enumerate() {
+                for b in &placed[i + 1..] {
+                    assert!(!a.icon.overlaps(&b.icon));
+                }
+                // everything moved remembers where it came from
+                assert_eq!(
+                    a.anchor.is_some(),
+                    (a.icon.x, a.icon.y) != (icons[i].x, icons[i].y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nudge_leaves_lone_icons_alone() {
+        let placed = nudge(vec![icon("a", 0.0, 0.0, 0), icon("a", 20.0, 0.0, 1)]);
+        assert!(placed.iter().all(|p| p.anchor.is_none() && p.count == 1));
+        assert_eq!(placed[1].icon.x, 20.0);
+    }
+}
@@ -0,0 +1,354 @@
+//! Machine-readable tables of what's on the map, for the wiki and for other tools.
+//! Everything is sorted, so that the files of two wars can be diffed.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::coords;
+use crate::hex_catalogue::HexCatalogue;
+use crate::world::World;
+
+/// How many of one icon type one team has in one hex.
+#[derive(Serialize)]
+pub struct CountRow<'a> {
+    pub hex: &'a str,
+    pub hex_name: &'a str,
+    pub icon: &'static str,
+    pub icon_file: &'static str,
+    pub team: &'static str,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct ItemRow<'a> {
+    pub hex: &'a str,
+    pub hex_name: &'a str,
+    pub icon: &'static str,
+    pub icon_file: &'static str,
+    pub team: &'static str,
+    /// the flags that are set, space separated
+    pub flags: String,
+    pub grid_ref: &'a str,
+    /// world coordinates, in metres
+    pub x: f32,
+    pub y: f32,
+    /// e.g. "120 m NE of Brine Glen"
+    pub location: Option<String>,
+    pub nearest_major: Option<&'a str>,
+    pub nearest_major_m: Option<f32>,
+    pub nearest_minor: Option<&'a str>,
+    pub nearest_minor_m: Option<f32>,
+}
+
+pub fn count_rows<'a>(
+    hex_catalogue: &'a HexCatalogue,
+    world: &'a World,
+) -> anyhow::Result<Vec<CountRow<'a>>> {
+    let mut counts = BTreeMap::new();
+    for item in &world.items {
+        let hex_name = hex_catalogue.get(&item.hex)?.display_name.as_str();
+        let key = (
+            hex_name,
+            item.hex.as_str(),
+            crate::get_icon_display_name(item.icon_type),
+            crate::team_name(item.team_id),
+        );
+        *counts.entry((key, item.icon_type)).or_insert(0) += 1;
+    }
+    Ok(counts
+        .into_iter()
+        .map(
+            |(((hex_name, hex, icon, team), icon_type), count)| CountRow {
+                hex,
+                hex_name,
+                icon,
+                icon_file: crate::get_icon_file_name(icon_type),
+                team,
+                count,
+            },
+        )
+        .collect())
+}
+
+pub fn item_rows<'a>(
+    hex_catalogue: &'a HexCatalogue,
+    world: &'a World,
+) -> anyhow::Result<Vec<ItemRow<'a>>> {
+    let mut rows = world
+        .items
+        .iter()
+        .map(|item| {
+            Ok(ItemRow {
+                hex: &item.hex,
+                hex_name: &hex_catalogue.get(&item.hex)?.display_name,
+                icon: crate::get_icon_display_name(item.icon_type),
+                icon_file: crate::get_icon_file_name(item.icon_type),
+                team: crate::team_name(item.team_id),
+                flags: crate::warapi_schema::flag_names(item.flags).join(" "),
+                grid_ref: &item.grid_ref,
+                x: crate::round_px(item.pos.x),
+                y: crate::round_px(item.pos.y),
+                location: item.nearest_place().map(|p| p.describe()),
+                nearest_major: item.nearest_major.as_ref().map(|p| p.name.as_str()),
+                nearest_major_m: item.nearest_major.as_ref().map(|p| p.distance_m.round()),
+                nearest_minor: item.nearest_minor.as_ref().map(|p| p.name.as_str()),
+                nearest_minor_m: item.nearest_minor.as_ref().map(|p| p.distance_m.round()),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    rows.sort_by(|a, b| {
+        (a.hex_name, a.icon, a.team, a.y, a.x)
+            .partial_cmp(&(b.hex_name, b.icon, b.team, b.y, b.x))
+            .unwrap()
+    });
+    Ok(rows)
+}
+
+/// Everything as GeoJSON: items and named locations as points, hexes as polygons.
+/// In world metres rather than longitude/latitude, with y flipped to grow northwards
+/// like GIS tools expect, so load it with no (or any metric) CRS.
+pub fn geojson(hex_catalogue: &HexCatalogue, world: &World) -> anyhow::Result<serde_json::Value> {
+    // rounded in f64, as f32s gain a lot of noisy digits when they become json numbers
+    let metres = |v: f32| (v as f64 * 100.0).round() / 100.0;
+    let point = |x: f32, y: f32| json!({"type": "Point", "coordinates": [metres(x), -metres(y)]});
+    let mut features = vec![];
+
+    let mut hexes = world
+        .hexes
+        .iter()
+        .map(|hex| hex_catalogue.get(hex))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    hexes.sort_by_key(|h| &h.display_name);
+    for hex in hexes {
+        // comes out counter-clockwise once flipped, as the spec wants. Has to be closed.
+        let mut ring = coords::hex_corners(hex.grid_coord)
+            .into_iter()
+            .map(|p| [metres(p.x), -metres(p.y)])
+            .collect::<Vec<_>>();
+        ring.push(ring[0]);
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {"type": "Polygon", "coordinates": [ring]},
+            "properties": {
+                "kind": "hex",
+                "hex": hex.name,
+                "hex_name": hex.display_name,
+                "grid_coord": [hex.grid_coord.0, hex.grid_coord.1],
+            },
+        }));
+    }
+
+    let mut places = world
+        .places
+        .iter()
+        .map(|p| Ok((hex_catalogue.get(&p.hex)?.display_name.as_str(), p)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    places.sort_by(|(a_hex, a), (b_hex, b)| (a_hex, &a.name).cmp(&(b_hex, &b.name)));
+    for (hex_name, place) in places {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": point(place.pos.x, place.pos.y),
+            "properties": {
+                "kind": "place",
+                "name": place.name,
+                "major": place.major,
+                "hex": place.hex,
+                "hex_name": hex_name,
+            },
+        }));
+    }
+
+    for row in item_rows(hex_catalogue, world)? {
+        let mut properties = serde_json::to_value(&row)?;
+        let properties = properties.as_object_mut().unwrap();
+        // they're in the geometry already
+        properties.remove("x");
+        properties.remove("y");
+        properties.insert("kind".into(), "item".into());
+        features.push(json!({
+            "type": "Feature",
+            "geometry": point(row.x, row.y),
+            "properties": properties,
+        }));
+    }
+
+    Ok(json!({"type": "FeatureCollection", "features": features}))
+}
+
+pub fn write_csv<T: Serialize>(path: &Path, rows: &[T]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path).with_context(|| format!("writing {:?}", path))?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(value)? + "\n")
+        .with_context(|| format!("writing {:?}", path))
+}
+
+/// Writes all the tables, and the GeoJSON, into the given directory.
+pub fn export_tables(
+    hex_catalogue: &HexCatalogue,
+    world: &World,
+    out_dir: &Path,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(out_dir).with_context(|| format!("creating {:?}", out_dir))?;
+
+    let counts = count_rows(hex_catalogue, world)?;
+    write_csv(&out_dir.join("counts.csv"), &counts)?;
+    write_json(&out_dir.join("counts.json"), &counts)?;
+
+    let items = item_rows(hex_catalogue, world)?;
+    write_csv(&out_dir.join("items.csv"), &items)?;
+    write_json(&out_dir.join("items.json"), &items)?;
+
+    write_json(
+        &out_dir.join("world.geojson"),
+        &geojson(hex_catalogue, world)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_hex_icon_and_team() {
+        let hex_catalogue = HexCatalogue::load().unwrap();
+        let world = World::new(&hex_catalogue, &crate::world::sample_maps()).unwrap();
+        let counts = count_rows(&hex_catalogue, &world)
+            .unwrap()
+            .iter()
+            .map(|r| (r.hex_name, r.icon, r.team, r.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                ("Callahan's Passage", "Coal Field", "neutral", 1),
+                ("Callahan's Passage", "Refinery", "Wardens", 1),
+                ("Callahan's Passage", "Town Base 3", "Wardens", 1),
+                ("Deadlands", "Hospital", "Colonials", 1),
+                ("Deadlands", "Salvage Field", "neutral", 2),
+                ("Deadlands", "Salvage Mine", "Wardens", 1),
+                ("Deadlands", "Town Base 3", "Colonials", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn items_sorted_with_where_they_are() {
+        let hex_catalogue = HexCatalogue::load().unwrap();
+        let world = World::new(&hex_catalogue, &crate::world::sample_maps()).unwrap();
+        let items = item_rows(&hex_catalogue, &world).unwrap();
+        let order = items
+            .iter()
+            .map(|r| (r.hex_name, r.icon, r.grid_ref))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [
+                ("Callahan's Passage", "Coal Field", "D13k8"),
+                ("Callahan's Passage", "Refinery", "J8k5"),
+                ("Callahan's Passage", "Town Base 3", "I8k5"),
+                ("Deadlands", "Hospital", "F11k2"),
+                // same y, so west to east
+                ("Deadlands", "Salvage Field", "F10k7"),
+                ("Deadlands", "Salvage Field", "K10k7"),
+                ("Deadlands", "Salvage Mine", "I4k8"),
+                ("Deadlands", "Town Base 3", "F11k4"),
+            ]
+        );
+        let town_base = items.last().unwrap();
+        assert_eq!(town_base.flags, "IsVictoryBase");
+        assert_eq!(town_base.location.as_deref(), Some("at Brine Glen"));
+        assert_eq!(
+            (town_base.nearest_minor, town_base.nearest_minor_m),
+            (Some("The Spine"), Some(1042.0))
+        );
+    }
+
+    #[test]
+    fn geojson_features() {
+        let hex_catalogue = HexCatalogue::load().unwrap();
+        let world = World::new(&hex_catalogue, &crate::world::sample_maps()).unwrap();
+        let geojson = geojson(&hex_catalogue, &world).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        let kinds = features
+            .iter()
+            .map(|f| f["properties"]["kind"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [["hex"; 2].as_slice(), &["place"; 3], &["item"; 8]].concat()
+        );
+
+        // closed, and counter-clockwise (positive area) with y going north
+        let deadlands = &features[1];
+        assert_eq!(deadlands["properties"]["hex"], "DeadLandsHex");
+        let ring = deadlands["geometry"]["coordinates"][0].as_array().unwrap();
+        let ring = ring
+            .iter()
+            .map(|p| (p[0].as_f64().unwrap(), p[1].as_f64().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(ring.len(), 7);
+        assert_eq!(ring[0], ring[6]);
+        let area = ring
+            .windows(2)
+            .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+            .sum::<f64>();
+        assert!(area > 0.0);
+
+        // Scorpion is north of the origin, so up in GIS terms
+        let scorpion = features
+            .iter()
+            .find(|f| f["properties"]["name"] == "Scorpion")
+            .unwrap();
+        let place = world.places.iter().find(|p| p.name == "Scorpion").unwrap();
+        assert!(place.pos.y < 0.0);
+        assert_eq!(
+            scorpion["geometry"]["coordinates"][1].as_f64().unwrap(),
+            -((place.pos.y as f64 * 100.0).round() / 100.0)
+        );
+
+        // and the items have their position only in the geometry
+        let item = &features[2 + 3];
+        assert_eq!(item["properties"]["icon"], "Coal Field");
+        assert!(item["properties"].get("x").is_none());
+        assert_eq!(item["geometry"]["type"], "Point");
+    }
+
+    #[test]
+    fn writes_all_the_files() {
+        let hex_catalogue = HexCatalogue::load().unwrap();
+        let world = World::new(&hex_catalogue, &crate::world::sample_maps()).unwrap();
+        let out_dir =
+            std::env::temp_dir().join(format!("mapmaker-test-export-{}", std::process::id()));
+        export_tables(&hex_catalogue, &world, &out_dir).unwrap();
+
+        let counts_csv = std::fs::read_to_string(out_dir.join("counts.csv")).unwrap();
+        let mut lines = counts_csv.lines();
+        assert_eq!(lines.next(), Some("hex,hex_name,icon,icon_file,team,count"));
+        assert_eq!(
+            lines.next(),
+            Some("CallahansPassageHex,Callahan's Passage,Coal Field,Coal,neutral,1")
+        );
+        assert_eq!(lines.count(), 6);
+        let items: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(out_dir.join("items.json")).unwrap())
+                .unwrap();
+        assert_eq!(items.len(), 8);
+        assert!(out_dir.join("items.csv").exists());
+        assert!(out_dir.join("counts.json").exists());
+        assert!(out_dir.join("world.geojson").exists());
+
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
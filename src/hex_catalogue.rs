@@ -0,0 +1,168 @@
+//! What the game itself knows about its hexes, from the datamined `BPMapList` blueprint.
+//! The warapi only gives us hex names, everything else (where the hex is on the grid,
+//! what it's called by humans, which texture it uses) comes from here.
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+const BP_MAP_LIST_FILE: &str = "data/datamine/War/Content/Blueprints/Data/BPMapList.json";
+
+// The raw shape of the blueprint export. Only the bits we care about.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BpMapList {
+    properties: BpMapListProperties,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BpMapListProperties {
+    map_database: Vec<MapDatabaseEntry>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MapDatabaseEntry {
+    key: String,
+    value: MapDatabaseValue,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MapDatabaseValue {
+    display_name: LocalizedText,
+    world_conquest_map_id: String,
+    image: ObjectReference,
+    #[serde(rename = "bIsInHexGrid")]
+    is_in_hex_grid: bool,
+    grid_coord: GridCoord,
+    #[serde(rename = "bIsIsland")]
+    is_island: bool,
+    #[serde(rename = "bCanOceanTravel")]
+    can_ocean_travel: bool,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LocalizedText {
+    source_string: String,
+    localized_string: String,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectReference {
+    object_path: String,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GridCoord {
+    x: i32,
+    y: i32,
+}
+
+pub struct HexInfo {
+    /// The internal name, which is also what the warapi calls the hex. E.g. `DeadLandsHex`
+    pub name: String,
+    /// The human name, e.g. "Deadlands"
+    pub display_name: String,
+    // the rest of what the datamine says about the hex, nothing needs them yet
+    #[allow(unused)]
+    pub localized_name: String,
+    /// E.g. `DeadLandsHex`, without the `EWorldConquestMapId::` prefix
+    #[allow(unused)]
+    pub world_conquest_map_id: String,
+    /// The game's path to the terrain texture, e.g.
+    /// `War/Content/Textures/UI/HexMaps/Processed/MapDeadlandsHex.0`
+    pub terrain_object_path: String,
+    /// Whether this is an actual hex of the world conquest map, as opposed to
+    /// the home regions, the sandbox, shooting range, event maps and such.
+    pub is_in_hex_grid: bool,
+    #[allow(unused)]
+    pub is_island: bool,
+    #[allow(unused)]
+    pub can_ocean_travel: bool,
+    /// Axial coordinates on the hex grid. The first one goes down-right, the second one down.
+    pub grid_coord: (i32, i32),
+}
+
+pub struct HexCatalogue {
+    hexes: BTreeMap<String, HexInfo>,
+}
+impl HexCatalogue {
+    pub fn load() -> anyhow::Result<Self> {
+        let bp_data: Vec<serde_json::Value> = serde_json::from_str(
+            &std::fs::read_to_string(BP_MAP_LIST_FILE)
+                .with_context(|| format!("reading {}", BP_MAP_LIST_FILE))?,
+        )
+        .with_context(|| format!("parsing {}", BP_MAP_LIST_FILE))?;
+        let maplist_c = bp_data
+            .into_iter()
+            .find(|v| v["Type"] == "BPMapList_C")
+            .with_context(|| format!("no BPMapList_C in {}", BP_MAP_LIST_FILE))?;
+        let maplist: BpMapList = serde_json::from_value(maplist_c)
+            .with_context(|| format!("parsing BPMapList_C in {}", BP_MAP_LIST_FILE))?;
+
+        let hexes = maplist
+            .properties
+            .map_database
+            .into_iter()
+            .map(|MapDatabaseEntry { key, value }| {
+                let info = HexInfo {
+                    name: key.clone(),
+                    display_name: value.display_name.source_string,
+                    localized_name: value.display_name.localized_string,
+                    world_conquest_map_id: value
+                        .world_conquest_map_id
+                        .trim_start_matches("EWorldConquestMapId::")
+                        .to_owned(),
+                    terrain_object_path: value.image.object_path,
+                    is_in_hex_grid: value.is_in_hex_grid,
+                    is_island: value.is_island,
+                    can_ocean_travel: value.can_ocean_travel,
+                    grid_coord: (value.grid_coord.x, value.grid_coord.y),
+                };
+                (key, info)
+            })
+            .collect();
+        Ok(Self { hexes })
+    }
+
+    /// A hex of the world conquest grid. Errors on things that aren't on it
+    /// (like the `Sandbox`, which would otherwise end up at -1,-1).
+    pub fn get(&self, name: &str) -> anyhow::Result<&HexInfo> {
+        let info = self
+            .hexes
+            .get(name)
+            .with_context(|| format!("{:?} is not in {}", name, BP_MAP_LIST_FILE))?;
+        anyhow::ensure!(info.is_in_hex_grid, "{:?} is not in the hex grid", name);
+        Ok(info)
+    }
+
+    /// Like `get`, but for things typed by humans: takes either the internal name
+    /// or the display name, and doesn't care about case.
+    pub fn find(&self, query: &str) -> anyhow::Result<&HexInfo> {
+        let lowercase = query.to_lowercase();
+        let info = self
+            .in_hex_grid()
+            .find(|h| {
+                h.name.to_lowercase() == lowercase || h.display_name.to_lowercase() == lowercase
+            })
+            .with_context(|| format!("no hex called {:?}", query))?;
+        Ok(info)
+    }
+
+    /// All the hexes of the world conquest grid, including ones the current war might not have.
+    pub fn in_hex_grid(&self) -> impl Iterator<Item = &HexInfo> {
+        self.hexes.values().filter(|h| h.is_in_hex_grid)
+    }
+}
+
+impl HexInfo {
+    /// Name of the terrain texture, e.g. `MapDeadlandsHex`.
+    /// The warapi repo has them as TGAs with the same name (give or take letter case).
+    pub fn terrain_texture_name(&self) -> &str {
+        let file = self
+            .terrain_object_path
+            .rsplit_once('/')
+            .map_or(self.terrain_object_path.as_str(), |(_, file)| file);
+        file.split_once('.').map_or(file, |(name, _)| name)
+    }
+}
@@ -0,0 +1,168 @@
+//! Which hexes border which, and how far apart they are.
+//! Built purely from the grid coordinates in the hex catalogue.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::hex_catalogue::HexInfo;
+
+/// The six neighbours on the axial grid, clockwise starting from the one straight up,
+/// together with their compass directions.
+/// (the first coordinate goes down-right, the second one straight down)
+pub const NEIGHBOUR_OFFSETS: [((i32, i32), &str); 6] = [
+    ((0, -1), "N"),
+    ((1, -1), "NE"),
+    ((1, 0), "SE"),
+    ((0, 1), "S"),
+    ((-1, 1), "SW"),
+    ((-1, 0), "NW"),
+];
+
+/// How many hexes you have to walk through to get from one coordinate to the other,
+/// were the grid infinite and without holes.
+pub fn grid_distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    let (dq, dr) = (a.0 - b.0, a.1 - b.1);
+    (dq.abs() + dr.abs() + (dq + dr).abs()) / 2
+}
+
+pub struct HexGraph {
+    coords: BTreeMap<String, (i32, i32)>,
+    by_coord: HashMap<(i32, i32), String>,
+}
+impl HexGraph {
+    pub fn new<'a>(hexes: impl IntoIterator<Item = &'a HexInfo>) -> Self {
+        let coords = hexes
+            .into_iter()
+            .map(|h| (h.name.clone(), h.grid_coord))
+            .collect::<BTreeMap<_, _>>();
+        let by_coord = coords.iter().map(|(n, &c)| (c, n.clone())).collect();
+        Self { coords, by_coord }
+    }
+
+    fn coord(&self, name: &str) -> anyhow::Result<(i32, i32)> {
+        self.coords
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow::format_err!("{:?} is not a hex in the graph", name))
+    }
+
+    /// The hexes bordering the given one, with the direction they are in.
+    pub fn neighbours(&self, name: &str) -> anyhow::Result<Vec<(&str, &'static str)>> {
+        let (q, r) = self.coord(name)?;
+        Ok(NEIGHBOUR_OFFSETS
+            .iter()
+            .filter_map(|&((dq, dr), direction)| {
+                self.by_coord
+                    .get(&(q + dq, r + dr))
+                    .map(|n| (n.as_str(), direction))
+            })
+            .collect())
+    }
+
+    /// Distance in hexes ("rings"), straight over the grid, whether or not there are
+    /// actual hexes on the way.
+    pub fn distance(&self, a: &str, b: &str) -> anyhow::Result<i32> {
+        Ok(grid_distance(self.coord(a)?, self.coord(b)?))
+    }
+
+    /// Shortest way from one hex to the other going only through the hexes in the graph,
+    /// including both ends. None if there is no way at all.
+    pub fn shortest_path(&self, from: &str, to: &str) -> anyhow::Result<Option<Vec<&str>>> {
+        self.coord(to)?;
+        // borrow the name from the graph rather than the caller, so the path can outlive it
+        let from = self
+            .coords
+            .get_key_value(from)
+            .map(|(k, _)| k.as_str())
+            .ok_or_else(|| anyhow::format_err!("{:?} is not a hex in the graph", from))?;
+
+        let mut came_from: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        came_from.insert(from, from);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![current];
+                let mut at = current;
+                while at != from {
+                    at = came_from[at];
+                    path.push(at);
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for (next, _) in self.neighbours(current)? {
+                if !came_from.contains_key(next) {
+                    came_from.insert(next, current);
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(name: &str, grid_coord: (i32, i32)) -> HexInfo {
+        HexInfo {
+            name: name.to_owned(),
+            display_name: name.to_owned(),
+            localized_name: name.to_owned(),
+            world_conquest_map_id: name.to_owned(),
+            terrain_object_path: String::new(),
+            is_in_hex_grid: true,
+            is_island: false,
+            can_ocean_travel: false,
+            grid_coord,
+        }
+    }
+
+    #[test]
+    fn shortest_paths() {
+        // a gap at (0,1), so going from (0,0) to (0,2) has to go around it, to the east.
+        // And a hex off on its own.
+        let hexes = [
+            hex("A", (0, 0)),
+            hex("B", (1, 0)),
+            hex("C", (1, 1)),
+            hex("D", (0, 2)),
+            hex("Lonely", (5, 5)),
+        ];
+        let graph = HexGraph::new(&hexes);
+        let cases: &[(&str, &str, Option<&[&str]>)] = &[
+            ("A", "A", Some(&["A"])),
+            ("A", "B", Some(&["A", "B"])),
+            ("A", "D", Some(&["A", "B", "C", "D"])),
+            ("D", "A", Some(&["D", "C", "B", "A"])),
+            ("A", "Lonely", None),
+            ("Lonely", "A", None),
+        ];
+        for &(from, to, expected) in cases {
+            assert_eq!(
+                graph.shortest_path(from, to).unwrap().as_deref(),
+                expected,
+                "{} -> {}",
+                from,
+                to
+            );
+        }
+        assert!(graph.shortest_path("A", "Nowhere").is_err());
+        assert!(graph.shortest_path("Nowhere", "A").is_err());
+    }
+
+    #[test]
+    fn distances() {
+        let cases = [
+            ((0, 0), (0, 0), 0),
+            ((0, 0), (0, 2), 2),
+            ((0, 0), (1, -1), 1),
+            ((0, 0), (1, 1), 2),
+            ((-2, 1), (2, -1), 4),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(grid_distance(a, b), expected, "{:?} -> {:?}", a, b);
+            assert_eq!(grid_distance(b, a), expected, "{:?} -> {:?}", b, a);
+        }
+    }
+}
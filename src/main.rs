@@ -2,9 +2,24 @@ use std::{collections::BTreeMap, io::Write};
 
 use anyhow::Context;
 
+mod coords;
+mod declutter;
+mod export;
+mod hex_catalogue;
+mod hex_graph;
+mod provenance;
+mod size_report;
+mod sprite_sheet;
+mod svg_check;
+mod tiles;
+mod viewer;
 mod warapi_schema;
+mod wikitext;
+mod world;
 
-const RESPONSE_CACHE_DIR: &'static str = "cache/warapi-response";
+const RESPONSE_CACHE_DIR: &str = "cache/warapi-response";
+// where the images go in linked asset mode, relative to the output svg
+const ASSETS_DIR: &str = "assets";
 
 #[derive(Clone, Copy)]
 enum Shard {
@@ -59,9 +74,8 @@ impl WarapiClient {
             .read_to_string()
             .unwrap();
         let war: warapi_schema::War = serde_json::from_str(&response_raw).unwrap();
-        let war_name: String;
-        match war.resistance_start_time {
-            None => war_name = format!("{}-{}", shard.name(), war.war_number),
+        let war_name = match war.resistance_start_time {
+            None => format!("{}-{}", shard.name(), war.war_number),
             Some(res_time) => {
                 let res_start_at = chrono::DateTime::from_timestamp_millis(res_time).unwrap();
                 let now = chrono::Utc::now();
@@ -70,7 +84,7 @@ impl WarapiClient {
                         "Too soon since resistance start, we are gonna mess things up. Aborting."
                     )
                 }
-                war_name = format!("{}-{}-resistance", shard.name(), war.war_number);
+                format!("{}-{}-resistance", shard.name(), war.war_number)
             }
         };
 
@@ -93,14 +107,26 @@ impl WarapiClient {
                 .write_all(response_raw.as_bytes())
                 .unwrap();
         };
-        let out = WarapiClient {
+        WarapiClient {
             agent,
             shard,
             // reuse_cache,
             // war,
             war_name,
-        };
-        out
+        }
+    }
+
+    /// For a war we've already got the responses of, without asking the warapi anything.
+    /// (As long as all of what we need is in the cache, that is.)
+    pub fn from_cache(agent: ureq::Agent, shard: Shard, war_name: &str) -> anyhow::Result<Self> {
+        let war_f =
+            &std::path::PathBuf::from(format!("{}/{}/war.json", RESPONSE_CACHE_DIR, war_name));
+        anyhow::ensure!(war_f.exists(), "no cached war at {:?}", war_f);
+        Ok(WarapiClient {
+            agent,
+            shard,
+            war_name: war_name.to_owned(),
+        })
     }
 
     fn _read_cached<T: serde::de::DeserializeOwned>(
@@ -143,23 +169,24 @@ impl WarapiClient {
         parsed
     }
 
+    /// The war as `new` got it, from the cache.
+    pub fn war(&self) -> warapi_schema::War {
+        self._read_cached("/worldconquest/war", "war")
+    }
     pub fn maps(&self) -> Vec<String> {
-        let maps = self._read_cached("/worldconquest/maps", "maps");
-        maps
+        self._read_cached("/worldconquest/maps", "maps")
     }
     pub fn map_static(&self, map: &str) -> warapi_schema::Map {
-        let map = self._read_cached(
-            &format!("/worldconquest/maps/{}/static", map),
-            &format!("maps/{}-static.json", map),
-        );
-        map
+        self._read_cached(
+            format!("/worldconquest/maps/{}/static", map),
+            format!("maps/{}-static.json", map),
+        )
     }
     pub fn map_dynamic(&self, map: &str) -> warapi_schema::Map {
-        let map = self._read_cached(
-            &format!("/worldconquest/maps/{}/dynamic/public", map),
-            &format!("maps/{}-dynamic.json", map),
-        );
-        map
+        self._read_cached(
+            format!("/worldconquest/maps/{}/dynamic/public", map),
+            format!("maps/{}-dynamic.json", map),
+        )
     }
     pub fn get_combined_map(&self, map: &str) -> warapi_schema::Map {
         let map_st = self.map_static(map);
@@ -168,95 +195,134 @@ impl WarapiClient {
         let mut out = map_st;
         assert!(out.map_items.is_empty());
         out.map_items = map_dy.map_items;
+        out.last_updated = out.last_updated.max(map_dy.last_updated);
         out
     }
 }
 
+// source: https://github.com/clapfoot/warapi?tab=readme-ov-file#map-icons
+// icon type, icon file name (`MapIcon{}.TGA`), human name
+const MAP_ICONS: &[(i32, &str, &str)] = &[
+    (8, "Forward Base 1", "Forward Base 1"),
+    (11, "Medical", "Hospital"),
+    (12, "Vehicle", "Vehicle Factory"),
+    (17, "Manufacturing", "Refinery"),
+    (18, "Shipyard", "Shipyard"),
+    (19, "TechCenter", "Tech Center"),
+    (20, "Salvage", "Salvage Field"),
+    (21, "Components", "Component Field"),
+    (22, "FuelField", "Fuel Field"),
+    (23, "Sulfur", "Sulfur Field"),
+    (24, "WorldMapTent", "World Map Tent"),
+    (25, "TravelTent", "Travel Tent"),
+    (26, "TrainingArea", "Training Area"),
+    (27, "Keep", "Special Base (Keep)"),
+    (28, "ObservationTower", "Observation Tower"),
+    (29, "Fort", "Fort"),
+    (30, "Troop Ship", "Troop Ship"),
+    (32, "SulfurMine", "Sulfur Mine"),
+    (33, "StorageFacility", "Storage Facility"),
+    (34, "Factory", "Factory"),
+    (35, "Safehouse", "Garrison Station"),
+    (37, "RocketSite", "Rocket Site"),
+    (38, "SalvageMine", "Salvage Mine"),
+    (39, "ConstructionYard", "Construction Yard"),
+    (40, "ComponentMine", "Component Mine"),
+    (45, "RelicBase", "Relic Base 1"),
+    (51, "MassProductionFactory", "Mass Production Factory"),
+    (52, "Seaport", "Seaport"),
+    (53, "CoastalGun", "Coastal Gun"),
+    (54, "SoulFactory", "Soul Factory"),
+    (56, "TownBaseTier1", "Town Base 1"),
+    (57, "TownBaseTier2", "Town Base 2"),
+    (58, "TownBaseTier3", "Town Base 3"),
+    (59, "StormCannon", "Storm Cannon"),
+    (60, "IntelCenter", "Intel Center"),
+    (61, "Coal", "Coal Field"),
+    (62, "OilWell", "Oil Field"),
+    (70, "RocketTarget", "Rocket Target"),
+    (71, "RocketGroundZero", "Rocket Ground Zero"),
+    (72, "RocketSiteWithRocket", "Rocket Site With Rocket"),
+    (75, "FacilityMineOilRig", "Facility Mine Oil Rig"),
+    (83, "WeatherStation", "Weather Station"),
+    (84, "MortarHouse", "Mortar House"),
+    // not in the readme (yet)
+    (88, "AircraftDepot", "Aircraft Depot"),
+    (89, "AircraftFactory", "Aircraft Factory"),
+    (90, "AircraftRadar", "Aircraft Radar"),
+    (91, "AircraftRunwayT1", "Aircraft Runway 1"),
+    (92, "AircraftRunwayT2", "Aircraft Runway 2"),
+];
+
+fn get_map_icon(icon_id: i32) -> &'static (i32, &'static str, &'static str) {
+    MAP_ICONS
+        .iter()
+        .find(|(id, _, _)| *id == icon_id)
+        .unwrap_or_else(|| unimplemented!("unknown icon type {:?}", icon_id))
+}
 fn get_icon_file_name(icon_id: i32) -> &'static str {
-    // source: https://github.com/clapfoot/warapi?tab=readme-ov-file#map-icons
-    match icon_id {
-        8 => "Forward Base 1", // Forward Base 1,
-
-        11 => "Medical",               // Hospital,
-        12 => "Vehicle",               // Vehicle Factory,
-        17 => "Manufacturing",         // Refinery,
-        18 => "Shipyard",              // Shipyard,
-        19 => "TechCenter",            // Tech Center,
-        20 => "Salvage",               // Salvage Field,
-        21 => "Components",            // Component Field,
-        22 => "FuelField",             // Fuel Field,
-        23 => "Sulfur",                // Sulfur Field,
-        24 => "WorldMapTent",          // World Map Tent,
-        25 => "TravelTent",            // Travel Tent,
-        26 => "TrainingArea",          // Training Area,
-        27 => "Keep",                  // Special Base (Keep),
-        28 => "ObservationTower",      // Observation Tower,
-        29 => "Fort",                  // Fort,
-        30 => "Troop Ship",            // Troop Ship,
-        32 => "SulfurMine",            // Sulfur Mine,
-        33 => "StorageFacility",       // Storage Facility,
-        34 => "Factory",               // Factory,
-        35 => "Safehouse",             // Garrison Station,
-        37 => "RocketSite",            // Rocket Site,
-        38 => "SalvageMine",           // Salvage Mine,
-        39 => "ConstructionYard",      // Construction Yard,
-        40 => "ComponentMine",         // Component Mine,
-        45 => "RelicBase",             // Relic Base 1,
-        51 => "MassProductionFactory", // Mass Production Factory,
-        52 => "Seaport",               // Seaport,
-        53 => "CoastalGun",            // Coastal Gun,
-        54 => "SoulFactory",           // Soul Factory,
-        56 => "TownBaseTier1",         // Town Base 1,
-        57 => "TownBaseTier2",         // Town Base 2,
-        58 => "TownBaseTier3",         // Town Base 3,
-        59 => "StormCannon",           // Storm Cannon,
-        60 => "IntelCenter",           // Intel Center,
-        61 => "Coal",                  // Coal Field,
-        62 => "OilWell",               // Oil Field,
-        70 => "RocketTarget",          // Rocket Target,
-        71 => "RocketGroundZero",      // Rocket Ground Zero,
-        72 => "RocketSiteWithRocket",  // Rocket Site With Rocket,
-        75 => "FacilityMineOilRig",    // Facility Mine Oil Rig,
-        83 => "WeatherStation",        // Weather Station,
-        84 => "MortarHouse",           // Mortar House,
-
-        88 => "AircraftDepot",
-        89 => "AircraftFactory",
-        90 => "AircraftRadar",
-        91 => "AircraftRunwayT1",
-        92 => "AircraftRunwayT2",
-        other => unimplemented!("unknown icon type {:?}", other),
-    }
-}
-
-struct HexCoordInfo {
-    hexes: BTreeMap<String, (i32, i32)>,
-}
-impl HexCoordInfo {
-    fn new() -> Self {
-        let mut hexes = BTreeMap::new();
-
-        let bp_file = "data/datamine/War/Content/Blueprints/Data/BPMapList.json";
-        let bp_data: Vec<serde_json::Value> =
-            serde_json::from_str(&std::fs::read_to_string(bp_file).unwrap()).unwrap();
-        let maplist_c = bp_data
-            .into_iter()
-            .find(|v| v["Type"] == "BPMapList_C")
-            .unwrap();
-        for v in maplist_c["Properties"]["MapDatabase"].as_array().unwrap() {
-            let name = v["Key"].as_str().unwrap().to_owned();
-            let x = v["Value"]["GridCoord"]["X"].as_i64().unwrap() as i32;
-            let y = v["Value"]["GridCoord"]["Y"].as_i64().unwrap() as i32;
-            hexes.insert(name, (x, y));
-        }
-        Self { hexes }
-    }
+    get_map_icon(icon_id).1
+}
+fn get_icon_display_name(icon_id: i32) -> &'static str {
+    get_map_icon(icon_id).2
+}
+
+/// The icon types a human might mean by the given name. Takes either the file name or the
+/// human name, and doesn't care about case or spaces, so "sulfur mine" and "SulfurMine" both work.
+fn find_icon_types(query: &str) -> Vec<i32> {
+    let normalize = |s: &str| s.replace(' ', "").to_lowercase();
+    let query = normalize(query);
+    MAP_ICONS
+        .iter()
+        .filter(|(_, file_name, display_name)| {
+            normalize(file_name) == query || normalize(display_name) == query
+        })
+        .map(|(id, _, _)| *id)
+        .collect()
+}
 
-    fn get_hex_coords(&self, name: &str) -> (i32, i32) {
-        return self.hexes[name];
+fn team_name(team_id: warapi_schema::TeamId) -> &'static str {
+    match team_id {
+        warapi_schema::TeamId::Colonials => "Colonials",
+        warapi_schema::TeamId::Wardens => "Wardens",
+        warapi_schema::TeamId::Nobody => "neutral",
     }
 }
 
+/// Colors for the base (black-and-white) icons, either to represent the faction
+/// (collie/warden) or just for readability (resources). Multiplied onto the icon.
+/// Colors taken from foxholestats
+const ICON_COLORS: &[(&str, [u8; 3])] = &[
+    ("Collie", [101, 135, 94]),
+    ("Warden", [72, 125, 169]),
+    ("Salvage", [154, 122, 85]),
+    ("Sulfur", [199, 199, 87]),
+    ("Coal", [75, 75, 75]),
+    ("Oil", [205, 107, 35]),
+    ("Components", [200, 200, 200]),
+];
+
+/// Which of the `ICON_COLORS` an icon gets, if any.
+fn icon_color(
+    icon_type: i32,
+    team_id: warapi_schema::TeamId,
+) -> Option<&'static (&'static str, [u8; 3])> {
+    use warapi_schema::TeamId;
+    let name = match team_id {
+        TeamId::Colonials => "Collie",
+        TeamId::Wardens => "Warden",
+        TeamId::Nobody => match get_icon_file_name(icon_type) {
+            "Salvage" | "SalvageMine" => "Salvage",
+            "Sulfur" | "SulfurMine" => "Sulfur",
+            "Coal" => "Coal",
+            "OilWell" => "Oil",
+            "Components" | "ComponentMine" => "Components",
+            _ => return None,
+        },
+    };
+    ICON_COLORS.iter().find(|(n, _)| *n == name)
+}
+
 fn make_map_icon_id(map_item: &warapi_schema::MapItem) -> String {
     let icon_file_name = get_icon_file_name(map_item.icon_type);
     let faction_suffix: &'static str = match map_item.team_id {
@@ -273,7 +339,330 @@ fn make_map_icon_base_id(map_item: &warapi_schema::MapItem) -> String {
     icon_id
 }
 
-fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_schema::Map)>) {
+/// Loads one of our own traced svg icons, to be used in place of the (blurry when zoomed in)
+/// TGA one. The icon becomes a `<symbol>`, and the element with the given id is a `<use>` of
+/// it, sized as if the icon was a TGA of its viewBox's dimensions. So the icons should be
+/// traced at the original pixel size. They should also be white, like the original ones,
+/// so that the color filters work the same on them.
+///
+/// All the ids in the icon get the given id as a prefix, as does everything that points at
+/// them, so that the icons can't step on each other's gradients and such. Editor
+/// leftovers (anything in someone else's namespace, and `<metadata>`) are left out.
+fn load_vector_icon(
+    path: &std::path::Path,
+    id: &str,
+    icon_scale_factor: f32,
+) -> anyhow::Result<(svg::node::element::Group, (f32, f32))> {
+    use svg::Node;
+    use svg::node::element::tag::Type;
+    use svg::parser::Event;
+
+    let raw = std::fs::read_to_string(path)?;
+    let prefixed = |value: &str| value.replace("url(#", &format!("url(#{}-", id));
+    let mut root_attrs = None;
+    let mut symbol = svg::node::element::Symbol::new().set("id", format!("{}-symbol", id));
+    // the elements we're inside of, below the root
+    let mut open: Vec<svg::node::element::Element> = vec![];
+    // how deep we are in an element that's left out
+    let mut skipping = 0;
+    for event in svg::read(&raw)? {
+        match event {
+            Event::Error(e) => return Err(e.into()),
+            Event::Tag("svg", Type::Start, attrs) if root_attrs.is_none() => {
+                root_attrs = Some(attrs);
+            }
+            Event::Tag(name, ty, attrs) => {
+                if skipping > 0 || name.contains(':') || name == "metadata" {
+                    match ty {
+                        Type::Start => skipping += 1,
+                        Type::End => skipping -= 1,
+                        Type::Empty => {}
+                    }
+                    continue;
+                }
+                let element = match ty {
+                    Type::Start | Type::Empty => {
+                        let mut element = svg::node::element::Element::new(name);
+                        for (attr, value) in attrs.iter() {
+                            let value = prefixed(value);
+                            match attr.as_str() {
+                                "id" => element.assign("id", format!("{}-{}", id, value)),
+                                "href" | "xlink:href" => match value.strip_prefix('#') {
+                                    Some(target) => {
+                                        element.assign("href", format!("#{}-{}", id, target))
+                                    }
+                                    None => element.assign("href", value),
+                                },
+                                attr if attr.contains(':') && !attr.starts_with("xml:") => {}
+                                attr => element.assign(attr, value),
+                            }
+                        }
+                        if ty == Type::Start {
+                            open.push(element);
+                            continue;
+                        }
+                        element
+                    }
+                    // the root's end, if there's nothing open
+                    Type::End => match open.pop() {
+                        Some(element) => element,
+                        None => continue,
+                    },
+                };
+                match open.last_mut() {
+                    Some(parent) => parent.append(element),
+                    None => symbol.append(element),
+                }
+            }
+            Event::Text(text) if skipping == 0 => {
+                if let Some(parent) = open.last_mut() {
+                    parent.append(svg::node::Blob::new(prefixed(text)));
+                }
+            }
+            _ => {}
+        }
+    }
+    let root_attrs = root_attrs.context("no root <svg> element")?;
+
+    let view_box = match (
+        root_attrs.get("viewBox"),
+        root_attrs.get("width"),
+        root_attrs.get("height"),
+    ) {
+        (Some(view_box), _, _) => view_box.to_string(),
+        (None, Some(w), Some(h)) => format!(
+            "0 0 {} {}",
+            w.trim_end_matches("px"),
+            h.trim_end_matches("px")
+        ),
+        _ => anyhow::bail!("need either a viewBox or width and height on the root element"),
+    };
+    let vb = view_box
+        .split([' ', ','])
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("bad viewBox {:?}", view_box))?;
+    anyhow::ensure!(vb.len() == 4, "bad viewBox {:?}", view_box);
+    let dims = (
+        (vb[2] * icon_scale_factor).round(),
+        (vb[3] * icon_scale_factor).round(),
+    );
+
+    let elem = svg::node::element::Group::new()
+        .add(symbol.set("viewBox", view_box))
+        .add(
+            svg::node::element::Use::new()
+                .set("id", id)
+                .set("href", format!("#{}-symbol", id))
+                .set("width", dims.0)
+                .set("height", dims.1),
+        );
+    Ok((elem, dims))
+}
+
+/// Finds the terrain image for each of the given hexes, going by the texture the game uses for it,
+/// unless overridden in the config. Errors with the list of all the hexes that have no terrain.
+fn resolve_terrain_paths(
+    cfg: &Config,
+    hex_catalogue: &hex_catalogue::HexCatalogue,
+    map_names: &[&str],
+) -> anyhow::Result<BTreeMap<String, std::path::PathBuf>> {
+    // The letter case of the file names does not always match the texture names
+    // (and that doesn't matter on windows), so we go case-insensitive.
+    let terrain_dir = &cfg.warapi_repo_path.join("Images").join("Maps");
+    let mut available = BTreeMap::new();
+    for entry in std::fs::read_dir(terrain_dir)
+        .with_context(|| anyhow::format_err!("listing {:?}", terrain_dir))?
+    {
+        let path = entry?.path();
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            available.insert(name.to_lowercase(), path.clone());
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    let mut missing = vec![];
+    for &map_name in map_names {
+        let filename = match cfg.terrain_overrides.get(map_name) {
+            Some(filename) => filename.clone(),
+            None => format!(
+                "{}.TGA",
+                hex_catalogue.get(map_name)?.terrain_texture_name()
+            ),
+        };
+        match available.get(&filename.to_lowercase()) {
+            Some(path) => {
+                out.insert(map_name.to_owned(), path.clone());
+            }
+            None => missing.push(format!("{} (looked for {})", map_name, filename)),
+        }
+    }
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "no terrain image in {:?} for:\n  {}\nfix it with `terrain_overrides` in the config",
+            terrain_dir,
+            missing.join("\n  ")
+        );
+    }
+    Ok(out)
+}
+
+/// Rounds to a hundredth of a pixel, which is plenty precise and keeps the numbers short.
+fn round_px(v: f32) -> f32 {
+    (v * 100.0).round() / 100.0
+}
+
+fn points_str(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|&(x, y)| format!("{},{}", round_px(x), round_px(y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A scale bar with its bottom left corner at the given point, for a map with the given scale.
+/// About a hex wide, rounded down to a nice number of metres, with the labels on top.
+fn draw_scale_bar(
+    px_per_metre: f32,
+    bottom_left: (f32, f32),
+    font_size: f32,
+) -> svg::node::element::Group {
+    // 1, 2 or 5 times some power of ten
+    let magnitude = 10f32.powf(coords::HEX_WIDTH_M.log10().floor());
+    let length_m = [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|f| f * magnitude)
+        .find(|&m| m <= coords::HEX_WIDTH_M)
+        .unwrap();
+    let segments = 4;
+    let (x0, y0) = bottom_left;
+    let bar_height = (font_size / 2.0).round();
+    let segment_px = length_m / segments as f32 * px_per_metre;
+
+    let mut scale_bar = svg::node::element::Group::new().set("id", "scale-bar");
+    for i in 0..segments {
+        scale_bar = scale_bar.add(
+            svg::node::element::Rectangle::new()
+                .set("x", round_px(x0 + i as f32 * segment_px))
+                .set("y", round_px(y0 - bar_height))
+                .set("width", round_px(segment_px))
+                .set("height", bar_height)
+                .set("fill", if i % 2 == 0 { "black" } else { "white" })
+                .set("stroke", "black")
+                .set("stroke-width", 1),
+        );
+    }
+    let mut labels = svg::node::element::Group::new()
+        .set("font-family", "sans-serif")
+        .set("font-weight", "bold")
+        .set("font-size", font_size)
+        .set("text-anchor", "middle")
+        .set("fill", "white")
+        .set("stroke", "black")
+        .set("stroke-width", round_px(font_size / 6.0))
+        .set("paint-order", "stroke");
+    for m in [0.0, length_m / 2.0, length_m] {
+        let text = match m {
+            m if m >= 1000.0 => format!("{} km", m / 1000.0),
+            m => format!("{} m", m),
+        };
+        labels = labels.add(
+            svg::node::element::Text::new(text)
+                .set("x", round_px(x0 + m * px_per_metre))
+                .set("y", round_px(y0 - bar_height - font_size * 0.4)),
+        );
+    }
+    scale_bar.add(labels)
+}
+
+/// A north arrow with an "N" on top, fitting a square of the given size at the given point.
+fn draw_compass(top_left: (f32, f32), size: f32) -> svg::node::element::Group {
+    let (x, y) = top_left;
+    let center_x = x + size / 2.0;
+    let tip = (center_x, y + size * 0.4);
+    let notch = (center_x, y + size * 0.85);
+    let (left, right) = (
+        (center_x - size * 0.25, y + size),
+        (center_x + size * 0.25, y + size),
+    );
+    let half = |points: &[(f32, f32)], fill: &str| {
+        svg::node::element::Polygon::new()
+            .set("points", points_str(points))
+            .set("fill", fill.to_owned())
+    };
+    svg::node::element::Group::new()
+        .set("id", "compass")
+        .set("stroke", "black")
+        .set("stroke-width", 1)
+        .set("stroke-linejoin", "round")
+        .add(half(&[tip, left, notch], "black"))
+        .add(half(&[tip, notch, right], "white"))
+        .add(
+            svg::node::element::Text::new("N")
+                .set("x", round_px(center_x))
+                .set("y", round_px(y + size * 0.3))
+                .set("font-size", round_px(size * 0.3))
+                .set("font-family", "sans-serif")
+                .set("font-weight", "bold")
+                .set("text-anchor", "middle")
+                .set("fill", "white")
+                .set("stroke-width", round_px(size * 0.05))
+                .set("paint-order", "stroke"),
+        )
+}
+
+/// Turns png bytes into something that can go into an `href`. Either a base64 data uri
+/// (the default - a single self-contained file, which is what the wiki wants), or,
+/// in linked mode, a path to a png written into the assets dir next to the svg.
+fn png_href(cfg: &Config, out_f: &std::path::Path, name: &str, png: &[u8]) -> String {
+    match cfg.asset_mode {
+        AssetMode::Embedded => format!("data:image/png;base64,{}", base64::encode(png)),
+        AssetMode::Linked => {
+            let asset_f = &out_f
+                .parent()
+                .unwrap()
+                .join(ASSETS_DIR)
+                .join(format!("{}.png", name));
+            std::fs::create_dir_all(asset_f.parent().unwrap()).unwrap();
+            std::fs::write(asset_f, png)
+                .with_context(|| anyhow::format_err!("writing {:?}", asset_f))
+                .unwrap();
+            format!("{}/{}.png", ASSETS_DIR, name)
+        }
+    }
+}
+
+// the scaling of just the terrain. Making them smaller in pixels also makes them smaller in filesize
+// which is valuable.
+const TERRAIN_RESIZE_FACTOR: f32 = 1.0 / 3.0;
+const ICON_SCALE_FACTOR: f32 = 1.0 / 6.0; // scaling of icons. No effect on file size or quality, pure svg
+
+/// The map as `draw_all_hexes` laid it out, and where things ended up on it, for the files
+/// that go along with the svg.
+struct DrawnMap<'a> {
+    document: svg::Document,
+    size_report: size_report::SizeReport,
+    /// where the world is on the image
+    layout: coords::CanvasTransform,
+    /// every hex that got drawn, with its corners on the image
+    hex_outlines: Vec<(&'a hex_catalogue::HexInfo, Vec<(f32, f32)>)>,
+    /// every icon, in the order they're drawn in
+    items: Vec<viewer::ViewerItem<'a>>,
+}
+
+/// Lays the whole map out as an svg that's going to be written to `out_f` (which only
+/// matters for where linked assets go).
+fn draw_all_hexes<'a>(
+    cfg: &Config,
+    hex_catalogue: &'a hex_catalogue::HexCatalogue,
+    maps: Vec<(String, warapi_schema::Map)>,
+    world: &world::World,
+    provenance: &provenance::Provenance,
+    out_f: &std::path::Path,
+) -> DrawnMap<'a> {
+    let warapi_repo_path = &cfg.warapi_repo_path;
+    let mut size_report = size_report::SizeReport::new(cfg.size_limit_bytes);
     let mut canvas = svg::Document::new();
     let mut worldbox = svg::node::element::Group::new().set("id", "worldbox");
     let mut defs = svg::node::element::Definitions::new();
@@ -282,32 +671,27 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
     let mut defs_icons_base = svg::node::element::Group::new().set("id", "icons-base-group");
     let mut def_composed_hexes = svg::node::element::Group::new();
 
+    // find all the terrains first, so that if any are missing we hear about all of them at once
+    let terrain_paths = resolve_terrain_paths(
+        cfg,
+        hex_catalogue,
+        &maps
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+
     let mut known_icon_dims = std::collections::HashMap::new();
+    let mut base_icons: Vec<(String, image::DynamicImage)> = vec![];
     let (mut terrain_width, mut terrain_height) = (0, 0); // uhh...
-    // the scaling of just the terrain. Making them smaller in pixels also makes them smaller in filesize
-    // which is valuable.
-    let terrain_resize_factor = 1.0 / 3.0;
-    let icon_scale_factor = 1.0 / 6.0; // scaling of icons. No effect on file size or quality, pure svg
     let global_scale_factor = 2.0; // the scaling of the overall image. No effect on file size, just presentation
     let mut composed_dims; // dimensions of the individual hexes, with everything on them
-    let mut eventual_bounds_px = (0, 0, 0, 0); // dimensions of the entire image
-
-    let hex_coord_info = HexCoordInfo::new();
+    let mut eventual_bounds_px = (0.0f32, 0.0f32, 0.0f32, 0.0f32); // dimensions of the entire image
 
     {
-        // svg filters for coloring base (black-and-white) icons, either to
-        // represent the faction (collie/warden) or just for readability (resources).
-        // Colors taken from foxholestats
-        let colors = vec![
-            ("Collie", [101, 135, 94]),
-            ("Warden", [72, 125, 169]),
-            ("Salvage", [154, 122, 85]),
-            ("Sulfur", [199, 199, 87]),
-            ("Coal", [75, 75, 75]),
-            ("Oil", [205, 107, 35]),
-            ("Components", [200, 200, 200]),
-        ];
-        for (name, values) in colors {
+        // svg filters for coloring base (black-and-white) icons, see `ICON_COLORS`
+        for &(name, values) in ICON_COLORS {
             let matrix = format!(
                 "{} 0 0 0 0\n0 {} 0 0 0\n0 0 {} 0 0\n0 0 0 1 0",
                 values[0] as f32 / 255.0,
@@ -323,11 +707,27 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
                         .set("values", matrix),
                 )
                 .set("id", format!("color{}", name));
+            size_report.add("filters", &format!("color{}", name), &filter);
             defs_icons = defs_icons.add(filter);
         }
     }
 
     let mut hex_canvas_coords = vec![];
+    let mut hexes_drawn = vec![];
+    let mut items = vec![];
+    // Icons (and anything else that goes on top) live in their own layer, drawn after all
+    // the terrain and the borders, so that the neighbouring hex's terrain can't cover up
+    // the icons sitting near the edge. One group per hex, in world coordinates.
+    let mut icon_layer = svg::node::element::Group::new().set("id", "icon-layer");
+    // Same for the labels, which go on top of the icons. The styling is shared by all of them.
+    let mut label_layer = svg::node::element::Group::new()
+        .set("id", "label-layer")
+        .set("font-family", "sans-serif")
+        .set("font-weight", "bold")
+        .set("text-anchor", "middle")
+        .set("fill", "white")
+        .set("stroke", "black")
+        .set("paint-order", "stroke");
     // iterate the maps one by one.
     // Load (and add) the terrain, then load any (missing) icons and add them too.
     for (map_name, map) in maps {
@@ -343,29 +743,20 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
         let composed_hex_id = &format!("composed-hex-{}", map_name);
         let tn_id = &format!("terrain-{}", map_name);
         let mut composed = svg::node::element::Group::new().set("id", composed_hex_id.clone());
+        let mut hex_icons =
+            svg::node::element::Group::new().set("id", format!("icons-hex-{}", map_name));
 
         // load the terrain
         {
-            // fixup clahstra naming discrepancy
-            // let map_name =
-            let filename = match map_name
-                .as_str()
-                .strip_suffix("Hex")
-                .unwrap_or(map_name.as_str())
-            {
-                // "ClahstraHex" => format!("Map{}Map.TGA", map_name),
-                other => format!("Map{}Hex.TGA", other),
-            };
-
-            let map_base_image = &warapi_repo_path.join("Images").join("maps").join(filename);
+            let map_base_image = &terrain_paths[&map_name];
             let terrain = image::ImageReader::open(map_base_image)
                 .with_context(|| anyhow::format_err!("reading {:?}", map_base_image))
                 .unwrap()
                 .decode()
                 .unwrap();
             let terrain = terrain.resize(
-                (terrain.width() as f32 * terrain_resize_factor).round() as u32,
-                (terrain.height() as f32 * terrain_resize_factor).round() as u32,
+                (terrain.width() as f32 * TERRAIN_RESIZE_FACTOR).round() as u32,
+                (terrain.height() as f32 * TERRAIN_RESIZE_FACTOR).round() as u32,
                 image::imageops::FilterType::Lanczos3,
             );
             if terrain_width != 0 {
@@ -385,29 +776,74 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
                 .set("id", tn_id.clone())
                 .set("width", terrain.width())
                 .set("height", terrain.height())
-                .set(
-                    "href",
-                    format!("data:image/png;base64,{}", base64::encode(tn_png.get_ref())),
-                );
+                .set("href", png_href(cfg, out_f, tn_id, tn_png.get_ref()));
+            size_report.add("terrain", &map_name, &tn);
             defs_terrain = defs_terrain.add(tn);
 
-            let u = svg::node::element::Use::new().set("href", format!("#{}", tn_id));
+            let u = svg::node::element::Use::new()
+                .set("href", format!("#{}", tn_id))
+                .set("clip-path", "url(#hex-clip)");
+            size_report.add("placements", &map_name, &u);
+            composed = composed.add(u);
+        }
+        if cfg.grid_overlay {
+            let u = svg::node::element::Use::new()
+                .set("href", "#hex-grid")
+                .set("clip-path", "url(#hex-clip)");
+            size_report.add("grid", &map_name, &u);
             composed = composed.add(u);
         }
 
         // Now do the icons
         // Sort them top-to-bottom for prettier layering. Works well for mines
         // We could probably also yeet safehouses and other boring things to the background, but that's tricky
-        let mut map_items = map.map_items;
-        map_items.sort_by_key(|it| ordered_float::OrderedFloat(it.y));
+        // (along with what else the world knows about them)
+        let mut map_items = map
+            .map_items
+            .into_iter()
+            .enumerate()
+            .map(|(i, mi)| {
+                let item = world
+                    .item(&map_name, i)
+                    .with_context(|| format!("map item {} of {} is not in the world", i, map_name))
+                    .unwrap();
+                (mi, item)
+            })
+            .collect::<Vec<_>>();
+        map_items.sort_by_key(|(it, _)| ordered_float::OrderedFloat(it.y));
+        let mut placed_icons = vec![];
+        // the icons go in the hex's group (see `icon_layer`), so in unscaled pixels from its
+        // top left
+        let hex_info = hex_catalogue.get(&map_name).unwrap();
+        let grid_coord = hex_info.grid_coord;
+        let hex_layout = {
+            let layout = coords::CanvasTransform::for_hex_width(terrain_width as f32);
+            let top_left = layout.to_canvas(coords::hex_top_left(grid_coord));
+            layout.shifted((-top_left.0, -top_left.1))
+        };
 
-        for mi in &map_items {
+        for (item_index, (mi, _)) in map_items.iter().enumerate() {
             let icon_id_for_map = &make_map_icon_id(mi);
             if !known_icon_dims.contains_key(icon_id_for_map) {
                 log::info!("adding {} and variants", icon_id_for_map);
                 let base_icon_id = &make_map_icon_base_id(mi);
 
-                // if we don't have the pixels - get the pixels
+                // if we don't have the pixels - get the pixels.
+                // Or the vectors, if we have our own traced version of the icon.
+                let vector_icon_path = cfg.vector_icon_dir.as_ref().map(|dir| {
+                    dir.join(format!("MapIcon{}.svg", get_icon_file_name(mi.icon_type)))
+                });
+                if !known_icon_dims.contains_key(base_icon_id)
+                    && let Some(vector_icon_path) = vector_icon_path.filter(|p| p.exists())
+                {
+                    let (icon_elem, dims) =
+                        load_vector_icon(&vector_icon_path, base_icon_id, ICON_SCALE_FACTOR)
+                            .with_context(|| format!("loading {:?}", vector_icon_path))
+                            .unwrap();
+                    size_report.add("icons-base", base_icon_id, &icon_elem);
+                    defs_icons_base = defs_icons_base.add(icon_elem);
+                    known_icon_dims.insert(base_icon_id.clone(), dims);
+                }
                 if !known_icon_dims.contains_key(base_icon_id) {
                     let icon_path = &warapi_repo_path
                         .join("Images")
@@ -424,30 +860,17 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
                         .decode()
                         .unwrap();
                     let (icon_width, icon_height) = (
-                        (icon.width() as f32 * icon_scale_factor).round(),
-                        (icon.height() as f32 * icon_scale_factor).round(),
+                        (icon.width() as f32 * ICON_SCALE_FACTOR).round(),
+                        (icon.height() as f32 * ICON_SCALE_FACTOR).round(),
                     );
-
-                    let mut base_icon_png = std::io::Cursor::new(vec![]);
-                    icon.write_to(&mut base_icon_png, image::ImageFormat::Png)
-                        .unwrap();
-                    let icon_elem = svg::node::element::Image::new()
-                        .set("id", base_icon_id.clone())
-                        .set("width", icon_width)
-                        .set("height", icon_height)
-                        .set(
-                            "href",
-                            format!(
-                                "data:image/png;base64,{}",
-                                base64::encode(base_icon_png.get_ref())
-                            ),
-                        );
-                    defs_icons_base = defs_icons_base.add(icon_elem.clone());
+                    // the actual svg elements are made once we've seen all the icons,
+                    // as they might all end up in one sprite sheet
+                    base_icons.push((base_icon_id.clone(), icon));
                     known_icon_dims.insert(base_icon_id.clone(), (icon_width, icon_height));
                 }
 
                 // we do have the pixels, but might not have the correct colors yet.
-                let base_icon_dims = known_icon_dims.get(base_icon_id).unwrap().clone();
+                let base_icon_dims = *known_icon_dims.get(base_icon_id).unwrap();
                 let factions = match mi.team_id {
                     // not making warden-colored fields - that's silly
                     warapi_schema::TeamId::Nobody => vec![warapi_schema::TeamId::Nobody],
@@ -469,45 +892,129 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
                         .set("id", icon_id_here.clone())
                         .set("href", format!("#{}", base_icon_id));
 
-                    use warapi_schema::TeamId;
-                    if let Some(filter) = match faction {
-                        TeamId::Colonials => Some("colorCollie"),
-                        TeamId::Wardens => Some("colorWarden"),
-                        TeamId::Nobody => match get_icon_file_name(new_mi.icon_type) {
-                            "Salvage" | "SalvageMine" => Some("colorSalvage"),
-                            "Sulfur" | "SulfurMine" => Some("colorSulfur"),
-                            "Coal" => Some("colorCoal"),
-                            "OilWell" => Some("colorOil"),
-                            "Components" | "ComponentMine" => Some("colorComponents"),
-                            _ => None,
-                        },
-                    } {
-                        icon_here = icon_here.set("filter", format!("url(#{})", filter));
+                    if let Some((color, _)) = icon_color(new_mi.icon_type, faction) {
+                        icon_here = icon_here.set("filter", format!("url(#color{})", color));
                     }
 
+                    size_report.add("icon-variants", &icon_id_here, &icon_here);
                     defs_icons = defs_icons.add(icon_here);
-                    known_icon_dims.insert(icon_id_here.clone(), base_icon_dims.clone());
+                    known_icon_dims.insert(icon_id_here.clone(), base_icon_dims);
                 }
             }
 
-            let (icon_width, icon_height) = known_icon_dims.get(icon_id_for_map).unwrap();
+            let (icon_width, icon_height) = *known_icon_dims.get(icon_id_for_map).unwrap();
+            let (x, y) = hex_layout.to_canvas(coords::hex_local_to_world(grid_coord, mi.x, mi.y));
+            placed_icons.push(declutter::PlacedIcon {
+                icon_id: icon_id_for_map.clone(),
+                x,
+                y,
+                width: icon_width,
+                height: icon_height,
+                item: item_index,
+            });
+        }
 
-            let tlx = (terrain_width as f32 * mi.x - icon_width / 2.0) as u32;
-            let tly = (terrain_height as f32 * mi.y - icon_height / 2.0) as u32;
-            let u = svg::node::element::Use::new()
-                .set("href", format!("#{}", icon_id_for_map))
+        let placements = declutter::declutter(placed_icons, cfg.declutter);
+        // leader lines from the nudged icons to where they really are, under all the icons
+        if placements.iter().any(|p| p.anchor.is_some()) {
+            let mut leaders = svg::node::element::Group::new()
+                .set("stroke", "black")
+                .set("stroke-width", 0.5);
+            for p in &placements {
+                if let Some((ax, ay)) = p.anchor {
+                    leaders = leaders.add(
+                        svg::node::element::Line::new()
+                            .set("x1", ax.round())
+                            .set("y1", ay.round())
+                            .set("x2", p.icon.x.round())
+                            .set("y2", p.icon.y.round()),
+                    );
+                }
+            }
+            size_report.add("declutter", &map_name, &leaders);
+            hex_icons = hex_icons.add(leaders);
+        }
+        let mut hex_items = vec![];
+        for p in &placements {
+            let tlx = (p.icon.x - p.icon.width / 2.0) as i32;
+            let tly = (p.icon.y - p.icon.height / 2.0) as i32;
+            let mut u = svg::node::element::Use::new()
+                .set("href", format!("#{}", p.icon.icon_id))
                 .set("x", tlx)
                 .set("y", tly);
-            composed = composed.add(u);
+            size_report.add("placements", &map_name, &u);
+            // what the html viewer shows when hovering over the icon. Kept out of the svg, as
+            // the wiki has no use for it. Where it is gets fixed up once the hex is placed.
+            {
+                let (_, item) = map_items[p.icon.item];
+                hex_items.push(viewer::ViewerItem {
+                    name: get_icon_display_name(item.icon_type),
+                    team: team_name(item.team_id),
+                    hex: &hex_info.display_name,
+                    grid: item.grid_ref.clone(),
+                    location: item.nearest_place().map(|place| place.describe()),
+                    flags: (item.flags != 0)
+                        .then(|| warapi_schema::flag_names(item.flags).join(" ")),
+                    count: (p.count > 1).then_some(p.count),
+                    x: tlx as f32,
+                    y: tly as f32,
+                    width: p.icon.width,
+                    height: p.icon.height,
+                });
+            }
+            // what browsers show when hovering over the icon, viewer or not
+            if cfg.icon_titles {
+                let (_, item) = map_items[p.icon.item];
+                let mut title = get_icon_display_name(item.icon_type).to_owned();
+                if p.count > 1 {
+                    title += &format!(" ×{}", p.count);
+                }
+                title += &format!(", {}", team_name(item.team_id));
+                if let Some(place) = item.nearest_place() {
+                    title += &format!(", {}", place.describe());
+                }
+                let without_title = u.to_string().len();
+                u = u.add(svg::node::element::Title::new(title));
+                size_report.add_bytes("titles", &map_name, u.to_string().len() - without_title);
+            }
+            hex_icons = hex_icons.add(u);
+
+            // count badge in the top right corner of the merged icons
+            if p.count > 1 {
+                let (cx, cy) = (
+                    (p.icon.x + p.icon.width / 2.0).round(),
+                    (p.icon.y - p.icon.height / 2.0).round(),
+                );
+                let badge = svg::node::element::Group::new()
+                    .add(
+                        svg::node::element::Circle::new()
+                            .set("cx", cx)
+                            .set("cy", cy)
+                            .set("r", 3.5)
+                            .set("fill", "white")
+                            .set("stroke", "black")
+                            .set("stroke-width", 0.5),
+                    )
+                    .add(
+                        svg::node::element::Text::new(p.count.to_string())
+                            .set("x", cx)
+                            .set("y", cy + 1.75)
+                            .set("font-size", 5)
+                            .set("font-family", "sans-serif")
+                            .set("text-anchor", "middle"),
+                    );
+                size_report.add("declutter", &map_name, &badge);
+                hex_icons = hex_icons.add(badge);
+            }
         }
         composed = composed.set("transform", format!("scale({})", global_scale_factor));
+        // The terrain is clipped to the exact hexagon (see `hex-clip`), so the hexes fit
+        // together seamlessly, and the borders drawn later line up with the seams.
+        let layout =
+            coords::CanvasTransform::for_hex_width(terrain_width as f32 * global_scale_factor);
         composed_dims = (
-            // the +2/+1 are for pretty white "borders" between hexes
-            // (explicitly drawing borders on top did not work out well at all -
-            //  drawing pixels looks bad, and drawing svg segments/polygons is VERY heavy
-            //  for the browser, somehow)
-            (terrain_width as f32 * global_scale_factor) as i32 - 1,
-            (terrain_height as f32 * global_scale_factor) as i32 - 1,
+            coords::HEX_WIDTH_M * layout.px_per_metre,
+            coords::HEX_HEIGHT_M * layout.px_per_metre,
         );
 
         // composed = composed.add(svg::node::element::Use::new().set("href", "#hex-border"));
@@ -516,84 +1023,258 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
 
         // now position the hex on the global grid
         {
-            let cos_30 = (std::f32::consts::PI / 180.0 * 30.0).cos();
-            let sin_30 = (std::f32::consts::PI / 180.0 * 30.0).sin();
-
             let hex_diameter_short = composed_dims.1;
-            let hex_coords = hex_coord_info.get_hex_coords(&map_name);
+            let hex_info = hex_catalogue.get(&map_name).unwrap();
+            let hex_coords = hex_info.grid_coord;
 
-            let global_offset_px = ((cos_30 * 4.0) * hex_diameter_short as f32, 0.0);
-            let offset_hexes = (
-                hex_coords.0 as f32 * cos_30,
-                hex_coords.0 as f32 * sin_30 + hex_coords.1 as f32,
-            );
-
-            let offset = (
-                (global_offset_px.0 + offset_hexes.0 * hex_diameter_short as f32).round(), // heigh
-                (global_offset_px.1 + offset_hexes.1 * hex_diameter_short as f32).round(),
-            );
+            let offset = layout.to_canvas(coords::hex_top_left(hex_coords));
+            let offset = (round_px(offset.0), round_px(offset.1));
 
             hex_canvas_coords.push(offset);
+            hexes_drawn.push(hex_info);
             let u = svg::node::element::Use::new()
                 .set("href", format!("#{}", composed_hex_id))
-                .set("x", offset.0 as i32)
-                .set("y", offset.1 as i32);
+                .set("x", offset.0)
+                .set("y", offset.1);
+            size_report.add("placements", "(hexes)", &u);
             worldbox = worldbox.add(u);
+            for item in &mut hex_items {
+                item.x = offset.0 + item.x * global_scale_factor;
+                item.y = offset.1 + item.y * global_scale_factor;
+                item.width *= global_scale_factor;
+                item.height *= global_scale_factor;
+            }
+            items.append(&mut hex_items);
+            icon_layer = icon_layer.add(hex_icons.set(
+                "transform",
+                format!(
+                    "translate({} {}) scale({})",
+                    offset.0, offset.1, global_scale_factor
+                ),
+            ));
+
+            // hex name, either in the middle or just under the top edge
+            // (which is usually empty, and so doesn't fight with the town names)
+            if cfg.hex_labels != HexLabels::None {
+                let font_size = (hex_diameter_short / 24.0).round();
+                let center_x = offset.0 + composed_dims.0 / 2.0;
+                let name_y = match cfg.hex_labels {
+                    HexLabels::Centre => offset.1 + composed_dims.1 / 2.0,
+                    _ => offset.1 + font_size * 1.5,
+                };
+                let mut label = svg::node::element::Group::new().add(
+                    svg::node::element::Text::new(hex_info.display_name.as_str())
+                        .set("x", round_px(center_x))
+                        .set("y", round_px(name_y))
+                        .set("font-size", font_size)
+                        .set("stroke-width", round_px(font_size / 6.0)),
+                );
+                if cfg.hex_label_grid_coords {
+                    let small_font_size = (font_size * 0.6).round();
+                    label = label.add(
+                        svg::node::element::Text::new(format!(
+                            "{}, {}",
+                            hex_coords.0, hex_coords.1
+                        ))
+                        .set("x", round_px(center_x))
+                        .set("y", round_px(name_y + small_font_size * 1.3))
+                        .set("font-size", small_font_size)
+                        .set("stroke-width", round_px(small_font_size / 6.0)),
+                    );
+                }
+                size_report.add("text", &map_name, &label);
+                label_layer = label_layer.add(label);
+            }
+
             eventual_bounds_px = (
-                eventual_bounds_px.0.min(offset.0 as i32),
-                eventual_bounds_px.1.min(offset.1 as i32),
-                eventual_bounds_px.2.max(offset.0 as i32 + composed_dims.0),
-                eventual_bounds_px.3.max(offset.1 as i32 + composed_dims.1),
+                eventual_bounds_px.0.min(offset.0),
+                eventual_bounds_px.1.min(offset.1),
+                eventual_bounds_px.2.max(offset.0 + composed_dims.0),
+                eventual_bounds_px.3.max(offset.1 + composed_dims.1),
             );
         }
         // break;
     }
 
-    // black borders around hexes
+    // the pixels of the base icons.
+    // Either one image per icon, or all of them packed into one sprite sheet, with each
+    // icon being a nested svg that only shows its own part of the sheet. In both cases
+    // the element has the base icon id and the icon's dimensions, so the variants
+    // and placements don't need to care which one it is.
+    if cfg.icon_sprite_sheet {
+        let sheet = sprite_sheet::pack(&base_icons.iter().map(|(_, im)| im).collect::<Vec<_>>());
+        let mut sheet_png = std::io::Cursor::new(vec![]);
+        sheet
+            .image
+            .write_to(&mut sheet_png, image::ImageFormat::Png)
+            .unwrap();
+        let sheet_id = "icon-sprite-sheet";
+        let sheet_elem = svg::node::element::Image::new()
+            .set("id", sheet_id)
+            .set("width", sheet.image.width())
+            .set("height", sheet.image.height())
+            .set("href", png_href(cfg, out_f, sheet_id, sheet_png.get_ref()));
+        size_report.add("icons-base", "(sprite sheet)", &sheet_elem);
+        defs_icons_base = defs_icons_base.add(sheet_elem);
+
+        for ((base_icon_id, _), (x, y, w, h)) in base_icons.iter().zip(sheet.rects) {
+            let (icon_width, icon_height) = known_icon_dims[base_icon_id];
+            let mut icon_elem = svg::node::element::SVG::new()
+                .set("id", base_icon_id.clone())
+                .set("width", icon_width)
+                .set("height", icon_height)
+                .set("viewBox", (x, y, w, h))
+                .set("preserveAspectRatio", "none")
+                .add(svg::node::element::Use::new().set("href", format!("#{}", sheet_id)));
+            // inherited from the root, no need to pay for it on every icon
+            icon_elem.get_attributes_mut().remove("xmlns");
+            size_report.add("icons-base", base_icon_id, &icon_elem);
+            defs_icons_base = defs_icons_base.add(icon_elem);
+        }
+    } else {
+        for (base_icon_id, icon) in &base_icons {
+            let (icon_width, icon_height) = known_icon_dims[base_icon_id];
+            let mut base_icon_png = std::io::Cursor::new(vec![]);
+            icon.write_to(&mut base_icon_png, image::ImageFormat::Png)
+                .unwrap();
+            let icon_elem = svg::node::element::Image::new()
+                .set("id", base_icon_id.clone())
+                .set("width", icon_width)
+                .set("height", icon_height)
+                .set(
+                    "href",
+                    png_href(cfg, out_f, base_icon_id, base_icon_png.get_ref()),
+                );
+            size_report.add("icons-base", base_icon_id, &icon_elem);
+            defs_icons_base = defs_icons_base.add(icon_elem);
+        }
+    }
+
+    // the shape every hex's terrain gets cut to. In terrain pixels, as it applies
+    // inside the (scaled) composed hex.
     {
-        let cos_30 = (std::f32::consts::PI / 180.0 * 30.0).cos();
-        let sin_30 = (std::f32::consts::PI / 180.0 * 30.0).sin();
+        let radius_long = terrain_width as f32 / 2.0;
+        let clip = svg::node::element::ClipPath::new()
+            .set("id", "hex-clip")
+            .add(svg::node::element::Polygon::new().set(
+                "points",
+                points_str(&coords::hex_polygon_points(radius_long)),
+            ));
+        size_report.add("terrain", "hex-clip", &clip);
+        defs = defs.add(clip);
+    }
+
+    // the in-game map grid, the same for every hex, so it's drawn once and used by all of them.
+    // Each cell gets its reference (like "G7") in its top left corner.
+    if cfg.grid_overlay {
+        // over the hex, which is a bit shorter than its terrain, like the grid references are
+        let w = terrain_width as f32;
+        let h = coords::HEX_HEIGHT_M * coords::CanvasTransform::for_hex_width(w).px_per_metre;
+        let (cell_w, cell_h) = (
+            w / coords::GRID_COLUMNS as f32,
+            h / coords::GRID_ROWS as f32,
+        );
+        let mut path_data = String::new();
+        for column in 1..coords::GRID_COLUMNS {
+            let x = round_px(column as f32 * cell_w);
+            path_data += &format!("M{},0V{}", x, round_px(h));
+        }
+        for row in 1..coords::GRID_ROWS {
+            let y = round_px(row as f32 * cell_h);
+            path_data += &format!("M0,{}H{}", y, w);
+        }
+        let mut grid = svg::node::element::Group::new().set("id", "hex-grid").add(
+            svg::node::element::Path::new()
+                .set("d", path_data)
+                .set("fill", "none")
+                .set("stroke", "black")
+                .set("stroke-opacity", 0.4)
+                .set("stroke-width", 0.5),
+        );
+        let font_size = round_px(cell_h / 5.0);
+        let mut cell_labels = svg::node::element::Group::new()
+            .set("font-family", "sans-serif")
+            .set("font-size", font_size)
+            .set("fill", "black")
+            .set("fill-opacity", 0.5);
+        for column in 0..coords::GRID_COLUMNS {
+            for row in 0..coords::GRID_ROWS {
+                let cell = format!("{}{}", (b'A' + column as u8) as char, row + 1);
+                cell_labels = cell_labels.add(
+                    svg::node::element::Text::new(cell)
+                        .set("x", round_px(column as f32 * cell_w + font_size / 4.0))
+                        .set("y", round_px(row as f32 * cell_h + font_size)),
+                );
+            }
+        }
+        grid = grid.add(cell_labels);
+        size_report.add("grid", "hex-grid", &grid);
+        defs = defs.add(grid);
+    }
 
+    // black borders around hexes, added after all the terrains so that they get drawn on top.
+    // Neighbouring hexes share edges, so rather than outlining every hex separately (drawing
+    // every inner edge twice, and being heavy on the browser) we collect the unique edges
+    // into a single path. Edges that only one hex has are the outline of the whole world,
+    // and get a bolder line.
+    {
         let radius_long = terrain_width as f32 / 2.0 * global_scale_factor;
-        // let radius_short = (composed_dims.1 / 2) as f32/ global_scale_factor;
-        let points = vec![
-            (-radius_long, 0.0),
-            (-radius_long * sin_30, radius_long * cos_30),
-            (radius_long * sin_30, radius_long * cos_30),
-            (radius_long, 0.0),
-            (radius_long * sin_30, -radius_long * cos_30),
-            (-radius_long * sin_30, -radius_long * cos_30),
-        ];
-        let points_str = points
-            .into_iter()
-            .map(|(x, y)| {
-                format!(
-                    "{},{}",
-                    ((x + radius_long) * 100.0).round() / 100.0,
-                    ((y + radius_long * cos_30) * 100.0).round() / 100.0
-                )
-            })
-            .collect::<Vec<_>>()
-            .join(" ");
-        let p = svg::node::element::Polygon::new()
-            .set("points", points_str)
+        let corners = coords::hex_polygon_points(radius_long);
+        // which of `hex_graph::NEIGHBOUR_OFFSETS` is on the other side of each edge,
+        // going around the corners of `hex_polygon_points`: SW, S, SE, NE, N, NW
+        const EDGE_NEIGHBOURS: [usize; 6] = [4, 3, 2, 1, 0, 5];
+        // keyed by the hex and the side, as seen from whichever of the two hexes sorts first.
+        // The pixels only come in once it's written out, as they don't round the same way
+        // for both hexes.
+        let mut edges = BTreeMap::new();
+        for (hex_info, (ox, oy)) in hexes_drawn.iter().zip(hex_canvas_coords) {
+            let (q, r) = hex_info.grid_coord;
+            let points = corners
+                .iter()
+                .map(|(x, y)| (x + ox, y + oy))
+                .collect::<Vec<_>>();
+            for (i, &side) in EDGE_NEIGHBOURS.iter().enumerate() {
+                let ((dq, dr), _) = hex_graph::NEIGHBOUR_OFFSETS[side];
+                let k = std::cmp::min(((q, r), side), ((q + dq, r + dr), (side + 3) % 6));
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                edges.entry(k).or_insert((a, b, 0)).2 += 1;
+            }
+        }
+        let path_data = |shared: bool| {
+            edges
+                .values()
+                .filter(|(_, _, count)| (*count > 1) == shared)
+                .map(|((ax, ay), (bx, by), _)| {
+                    format!(
+                        "M{},{}L{},{}",
+                        round_px(*ax),
+                        round_px(*ay),
+                        round_px(*bx),
+                        round_px(*by)
+                    )
+                })
+                .collect::<String>()
+        };
+        let interior = svg::node::element::Path::new()
+            .set("id", "hex-borders")
+            .set("d", path_data(true))
             .set("fill", "none")
             .set("stroke", "black")
-            .set("stroke-width", 1)
-            .set("id", "hex-border");
-        // composed = composed.add(p);
-        defs = defs.add(p)
-    }
-
-    // added after all the terrains so that it gets drawn on top of it
-    for (x, y) in hex_canvas_coords {
-        worldbox = worldbox.add(
-            svg::node::element::Use::new()
-                .set("href", "#hex-border")
-                .set("x", x)
-                .set("y", y), // .set("transform",
-        )
+            .set("stroke-width", 1);
+        let outline = svg::node::element::Path::new()
+            .set("id", "world-outline")
+            .set("d", path_data(false))
+            .set("fill", "none")
+            .set("stroke", "black")
+            .set("stroke-width", 3)
+            .set("stroke-linecap", "round");
+        size_report.add("borders", "hex-borders", &interior);
+        size_report.add("borders", "world-outline", &outline);
+        worldbox = worldbox.add(interior).add(outline);
     }
+    // and the icons on top of everything, and the labels on top of those
+    worldbox = worldbox.add(icon_layer);
+    worldbox = worldbox.add(label_layer);
 
     // combine the svg parts together, and write the file out
     defs = defs.add(worldbox);
@@ -602,7 +1283,12 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
     defs = defs.add(defs_icons_base);
     defs = defs.add(defs_terrain);
 
+    let metadata = provenance.metadata_element();
+    size_report.add("metadata", "metadata", &metadata);
+    canvas = canvas.add(metadata);
+
     let (xmin, ymin, xmax, ymax) = eventual_bounds_px;
+    let (canvas_width, canvas_height) = ((xmax - xmin).ceil(), (ymax - ymin).ceil());
     canvas = canvas
         .add(
             svg::node::element::Use::new()
@@ -610,39 +1296,314 @@ fn draw_all_hexes(warapi_repo_path: &std::path::Path, maps: Vec<(String, warapi_
                 .set("x", -xmin)
                 .set("y", -ymin),
         )
-        .set("width", xmax - xmin)
-        .set("height", ymax - ymin);
+        .set("width", canvas_width)
+        .set("height", canvas_height);
+    // where the world ends up on the final image
+    let layout = coords::CanvasTransform::for_hex_width(terrain_width as f32 * global_scale_factor)
+        .shifted((-xmin, -ymin));
+
+    // the scale bar and the compass go into the corners of the image, which are always empty
+    // as the world is roughly a hexagon itself. Sized relative to the hexes, like the labels.
+    {
+        let hex_height_px = coords::HEX_HEIGHT_M * layout.px_per_metre;
+        let font_size = (hex_height_px / 24.0).round();
+        let margin = font_size * 2.0;
+        let scale_bar = draw_scale_bar(
+            layout.px_per_metre,
+            (margin, canvas_height - margin),
+            font_size,
+        );
+        size_report.add("overlays", "scale-bar", &scale_bar);
+        canvas = canvas.add(scale_bar);
+        if cfg.compass {
+            let compass = draw_compass((margin, margin), (hex_height_px / 4.0).round());
+            size_report.add("overlays", "compass", &compass);
+            canvas = canvas.add(compass);
+        }
+    }
 
     canvas = canvas.add(defs);
 
     // let mut worldbox = svg::node::element::SVG
 
-    let out_f = &std::path::PathBuf::from("tmp/out.svg");
-    std::fs::create_dir_all(out_f.parent().unwrap()).unwrap();
-    svg::save(out_f, &canvas).unwrap();
-    log::info!("Written to {}", out_f.display());
+    // the outlines of the hexes where they ended up, for the clickable image map
+    let hex_outlines = hexes_drawn
+        .into_iter()
+        .map(|hex| {
+            let corners = coords::hex_corners(hex.grid_coord)
+                .into_iter()
+                .map(|corner| layout.to_canvas(corner))
+                .collect();
+            (hex, corners)
+        })
+        .collect();
+
+    // and the icons, to where the world ended up
+    for item in &mut items {
+        item.x = round_px(item.x - xmin);
+        item.y = round_px(item.y - ymin);
+    }
+
+    DrawnMap {
+        document: canvas,
+        size_report,
+        layout,
+        hex_outlines,
+        items,
+    }
 }
 
-fn do_stuff(cfg: &Config, opts: &Options) {
+/// Checks the svg against the wiki's upload rules, logging whatever is wrong with it.
+fn validate_svg(cfg: &Config, svg_f: &std::path::Path) -> anyhow::Result<bool> {
+    let problems = svg_check::check(svg_f, cfg.size_limit_bytes)?;
+    if problems.is_empty() {
+        log::info!("{} looks fine for the wiki", svg_f.display());
+        return Ok(true);
+    }
+    log::error!(
+        "{} would not make it onto the wiki, {} problem(s):",
+        svg_f.display(),
+        problems.len()
+    );
+    for problem in &problems {
+        log::error!("  {}", problem);
+    }
+    Ok(false)
+}
+
+/// The warapi client for the shard, for its current war. Or for the given war (like
+/// `able-128`) from what's in the response cache, without asking the warapi anything.
+fn warapi_client(shard: Shard, cached_war: Option<&str>) -> anyhow::Result<WarapiClient> {
     let agent = ureq::Agent::new_with_defaults();
-    let shard = opts.shard;
-    // let shard = Shard::Devbranch;
-    let client = WarapiClient::new(agent, shard);
+    match cached_war {
+        Some(war) => WarapiClient::from_cache(agent, shard, war),
+        None => Ok(WarapiClient::new(agent, shard)),
+    }
+}
 
-    let maps = client
+/// The combined maps of all the hexes of the current war that are on the hex grid.
+fn load_maps(
+    client: &WarapiClient,
+    hex_catalogue: &hex_catalogue::HexCatalogue,
+) -> Vec<(String, warapi_schema::Map)> {
+    client
         .maps()
         .iter()
+        // should never happen, but we'd rather not draw home regions on top of Deadlands
+        .filter(|mapname| match hex_catalogue.get(mapname) {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("skipping {}: {:#}", mapname, e);
+                false
+            }
+        })
         .map(|mapname| (mapname.clone(), client.get_combined_map(mapname)))
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+fn make_provenance(
+    cfg: &Config,
+    client: &WarapiClient,
+    maps: &[(String, warapi_schema::Map)],
+) -> provenance::Provenance {
+    let war = client.war();
+    provenance::Provenance {
+        endpoint: client.shard.root_endpoint(),
+        shard: client.shard.name(),
+        war_id: war.war_id,
+        war_number: war.war_number,
+        snapshot_time: maps
+            .iter()
+            .map(|(_, map)| map.last_updated)
+            .max()
+            .and_then(chrono::DateTime::from_timestamp_millis),
+        warapi_commit: provenance::repo_commit(&cfg.warapi_repo_path),
+    }
+}
+
+fn do_stuff(cfg: &Config, shard: Shard) -> anyhow::Result<()> {
+    // let shard = Shard::Devbranch;
+    check_warapi_repo(cfg)?;
+    let client = warapi_client(shard, None)?;
+
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let maps = load_maps(&client, &hex_catalogue);
+
+    let provenance = make_provenance(cfg, &client, &maps);
+
+    let world = world::World::new(&hex_catalogue, &maps)?;
 
     // let warapi_repo_path = std::path::Path::new(WARAPI_REPO_PATH);
-    draw_all_hexes(&cfg.warapi_repo_path, maps);
+    let out_f = &std::path::PathBuf::from("tmp/out.svg");
+    let DrawnMap {
+        document,
+        size_report,
+        layout,
+        hex_outlines,
+        items,
+    } = draw_all_hexes(cfg, &hex_catalogue, maps, &world, &provenance, out_f);
+
+    std::fs::create_dir_all(out_f.parent().unwrap())
+        .with_context(|| format!("creating {:?}", out_f.parent()))?;
+    svg::save(out_f, &document).with_context(|| format!("writing {:?}", out_f))?;
+    log::info!("Written to {}", out_f.display());
+
+    // clickable hexes for the wiki, matching the svg pixel for pixel
+    {
+        let hex_polygons = hex_outlines
+            .into_iter()
+            .map(|(hex, corners)| wikitext::ImagemapHex {
+                page: cfg
+                    .imagemap_pages
+                    .get(&hex.name)
+                    .unwrap_or(&hex.display_name),
+                title: &hex.display_name,
+                corners,
+            })
+            .collect::<Vec<_>>();
+        let imagemap_f = &out_f.with_extension("imagemap.wikitext");
+        std::fs::write(
+            imagemap_f,
+            wikitext::imagemap(&cfg.imagemap_file_name, &hex_polygons),
+        )
+        .with_context(|| format!("writing {:?}", imagemap_f))?;
+        log::info!("Image map written to {}", imagemap_f.display());
+    }
+    // and the text for the file's page on the wiki
+    {
+        let description_f = &out_f.with_extension("description.wikitext");
+        std::fs::write(description_f, provenance.file_description())
+            .with_context(|| format!("writing {:?}", description_f))?;
+        log::info!("File description written to {}", description_f.display());
+    }
+    // the svg again, wrapped into a page you can pan around and hover over things in
+    if cfg.html_viewer {
+        let places = world
+            .places
+            .iter()
+            .map(|place| {
+                Ok(viewer::ViewerPlace {
+                    name: &place.name,
+                    hex: &hex_catalogue.get(&place.hex)?.display_name,
+                    major: place.major,
+                    x: round_px(layout.to_canvas(place.pos).0),
+                    y: round_px(layout.to_canvas(place.pos).1),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let html_f = &out_f.with_extension("html");
+        viewer::write_html(
+            html_f,
+            &document.to_string(),
+            &provenance.title(),
+            &places,
+            &items,
+        )?;
+        log::info!("Viewer written to {}", html_f.display());
+    }
+
+    let file_bytes = std::fs::metadata(out_f)
+        .with_context(|| format!("reading {:?}", out_f))?
+        .len() as usize;
+    size_report.print_table(file_bytes);
+    let report_f = &out_f.with_extension("size-report.json");
+    size_report.write_json(report_f, file_bytes);
+    log::info!("Size report written to {}", report_f.display());
+
+    // and make sure the wiki is going to take it
+    if cfg.asset_mode == AssetMode::Linked {
+        log::warn!(
+            "not checking the svg against the wiki's rules, as linked assets can't go there anyway"
+        );
+    } else {
+        anyhow::ensure!(
+            validate_svg(cfg, out_f)?,
+            "{} is not ready for the wiki",
+            out_f.display()
+        );
+    }
+    Ok(())
 }
 
 #[derive(serde::Deserialize)]
 struct Config {
     warapi_repo_path: std::path::PathBuf,
     yino_repo_path: Option<std::path::PathBuf>,
+    /// The file size we want the output svg to stay under. Defaults to the wiki upload limit.
+    #[serde(default = "default_size_limit_bytes")]
+    size_limit_bytes: usize,
+    #[serde(default)]
+    asset_mode: AssetMode,
+    /// Pack all the base icons into one image instead of embedding each one separately.
+    #[serde(default)]
+    icon_sprite_sheet: bool,
+    /// What to do about icons overlapping each other.
+    #[serde(default)]
+    declutter: declutter::DeclutterMode,
+    /// Terrain image file names (within `Images/Maps` of the warapi repo) for hexes whose
+    /// name doesn't match the texture the game says they use.
+    #[serde(default)]
+    terrain_overrides: BTreeMap<String, String>,
+    /// Whether and where to put the hex names on the map.
+    #[serde(default)]
+    hex_labels: HexLabels,
+    /// Put the hex's grid coordinate under its name.
+    #[serde(default)]
+    hex_label_grid_coords: bool,
+    /// Directory with our own vector versions of the icons, named like the TGAs
+    /// (`MapIconSalvage.svg`). Icons that don't have one there use the TGA.
+    #[serde(default)]
+    vector_icon_dir: Option<std::path::PathBuf>,
+    /// Draw the in-game map grid (A-Q, 1-15) over every hex.
+    #[serde(default)]
+    grid_overlay: bool,
+    /// What the svg is called on the wiki, for the `<imagemap>` written next to it.
+    #[serde(default = "default_imagemap_file_name")]
+    imagemap_file_name: String,
+    /// Wiki pages for the hexes in the `<imagemap>`, for the ones where it isn't just the
+    /// hex's display name. Keyed by the internal hex name, like `DeadLandsHex`.
+    #[serde(default)]
+    imagemap_pages: BTreeMap<String, String>,
+    /// Also write an html page next to the svg, for looking at the map in a browser
+    /// with pan and zoom, tooltips on the icons, layer toggles and a town search.
+    /// Puts the tooltip data onto the icons in the svg too, which costs some bytes.
+    #[serde(default)]
+    html_viewer: bool,
+    /// Give every icon a `<title>` (what it is, whose it is, and where), which browsers
+    /// show as a tooltip. Shows up as `titles` in the size report.
+    #[serde(default)]
+    icon_titles: bool,
+    /// Put a north arrow in the top left corner. (The scale bar is always there.)
+    #[serde(default)]
+    compass: bool,
+}
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum HexLabels {
+    #[default]
+    None,
+    /// just under the top edge of the hex
+    Top,
+    /// in the middle of the hex
+    Centre,
+}
+
+/// How the terrain and icon images end up in the svg.
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum AssetMode {
+    /// base64 data uris, everything in one file. Good for uploading to the wiki.
+    #[default]
+    Embedded,
+    /// separate png files referenced by relative path. Good for self-hosting,
+    /// as the browser can cache and load them in parallel.
+    Linked,
+}
+fn default_size_limit_bytes() -> usize {
+    10 * 1024 * 1024
+}
+fn default_imagemap_file_name() -> String {
+    "Map.svg".to_owned()
 }
 fn read_config() -> Config {
     use std::path::Path;
@@ -657,21 +1618,20 @@ fn read_config() -> Config {
         std::fs::copy(config_example_path, config_path).unwrap();
     }
     let cfg: Config = json5::from_str(&std::fs::read_to_string(config_path).unwrap()).unwrap();
-
-    // you know what, let's verify the config while at it...
-    {
-        if !cfg.warapi_repo_path.exists() {
-            log::error!(
-                "Your config at {} specifies {} as the path for warapi repo, but that doesn't exist. Download it from https://github.com/clapfoot/warapi or tweak the path, idk.",
-                config_path.display(),
-                cfg.warapi_repo_path.display(),
-            );
-            std::process::exit(1);
-        }
-    }
     cfg
 }
 
+/// For whatever needs the icons and the terrain from the warapi repo, which the subcommands
+/// that only ask the warapi don't.
+fn check_warapi_repo(cfg: &Config) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        cfg.warapi_repo_path.exists(),
+        "the warapi repo is expected at {}, but that doesn't exist. Download it from https://github.com/clapfoot/warapi, or run without --skip-git, idk.",
+        cfg.warapi_repo_path.display(),
+    );
+    Ok(())
+}
+
 fn fetch_git_repo(repo_url: &str, repo_path: &std::path::Path) {
     if !repo_path.exists() {
         std::fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
@@ -719,48 +1679,475 @@ fn fetch_git_repo(repo_url: &str, repo_path: &std::path::Path) {
         )
         .with_context(|| anyhow::format_err!("failed to fetch"))
         .unwrap();
-    let origin_master_rev = repo
-        .revparse(&format!("origin/{}", default_branch))
-        .unwrap();
+    checkout_fetched(&repo, default_branch);
+}
+
+/// Checks out what was fetched of the branch, with HEAD pointing at it, as the provenance
+/// goes by HEAD.
+fn checkout_fetched(repo: &git2::Repository, branch: &str) {
+    let origin_master_rev = repo.revparse_single(&format!("origin/{}", branch)).unwrap();
     repo.checkout_tree(
-        origin_master_rev.from().unwrap(),
+        &origin_master_rev,
         Some(git2::build::CheckoutBuilder::new().force()),
     )
     .unwrap();
+    repo.set_head_detached(origin_master_rev.id()).unwrap();
 }
 
 #[derive(bpaf::Bpaf)]
 #[bpaf(options)]
-struct Options {
-    #[bpaf(long)]
+enum Options {
+    // draws the map, unless told to do something else
+    Draw {
+        #[bpaf(long)]
+        shard: Shard,
+        #[bpaf(long)]
+        skip_git: bool,
+    },
+    Command(#[bpaf(external(command))] Command),
+}
+
+#[derive(bpaf::Bpaf, Clone)]
+enum Command {
+    /// Hex grid queries. With one hex, lists its neighbours.
+    /// With two, the distance and the shortest path between them.
+    #[bpaf(command)]
+    Hexes {
+        /// Hex name, either like `DeadLandsHex` or `Deadlands`
+        #[bpaf(positional("HEX"))]
+        hex: String,
+        #[bpaf(positional("OTHER_HEX"))]
+        other_hex: Option<String>,
+    },
+    /// Straight-line distance between two things on the map, each either a named location
+    /// or a kind of map item. Picks the closest pair, so `distance seaport "sulfur mine"`
+    /// finds the sulfur mine closest to any seaport.
+    #[bpaf(command)]
+    Distance {
+        #[bpaf(long)]
+        shard: Shard,
+        /// Location name like `Abandoned Ward`, or icon type like `Seaport` or `Sulfur Mine`
+        #[bpaf(positional("FROM"))]
+        from: String,
+        #[bpaf(positional("TO"))]
+        to: String,
+    },
+    /// Lists the map items hex by hex, along with where they are relative to the nearest
+    /// Major and Minor locations.
+    #[bpaf(command)]
+    Items {
+        #[bpaf(long)]
+        shard: Shard,
+        /// Only the items in this hex
+        #[bpaf(long, argument("HEX"))]
+        hex: Option<String>,
+        /// Only the items of this type, like `Salvage Mine`
+        #[bpaf(positional("TYPE"))]
+        icon_type: Option<String>,
+    },
+    /// Writes tables of what's on the map: counts per hex, icon type and team,
+    /// and every single item with where it is. Both as CSV and as JSON.
+    /// Plus all of it as GeoJSON, with the hex outlines, and the wikitext tables for
+    /// the starting conditions page.
+    #[bpaf(command)]
+    Export {
+        #[bpaf(long)]
+        shard: Shard,
+        /// Where to put the files
+        #[bpaf(long, argument("DIR"), fallback("tmp/export".into()))]
+        out_dir: std::path::PathBuf,
+    },
+    /// Renders the terrain, at full resolution, and the icons into a z/x/y pyramid of png
+    /// tiles for a zoomable map like Leaflet, plus a `manifest.json` with the zoom levels
+    /// and how pixels relate to the world. Tiles with nothing on them are left out.
+    /// Uses the warapi repo the map gets drawn from as it is, without fetching it.
+    #[bpaf(command)]
+    Tiles {
+        #[bpaf(long)]
+        shard: Shard,
+        /// Where to put the tiles
+        #[bpaf(long, argument("DIR"), fallback("tmp/tiles".into()))]
+        out_dir: std::path::PathBuf,
+        /// Draw this war (like `able-128`) from the cached warapi responses, without asking
+        /// the warapi which war it is. For when there's no network.
+        #[bpaf(long, argument("WAR"))]
+        war: Option<String>,
+    },
+    /// Checks an svg against the wiki's upload rules and the configured size limit.
+    /// Drawing the map does this too.
+    #[bpaf(command)]
+    Validate {
+        #[bpaf(positional("SVG"), fallback("tmp/out.svg".into()))]
+        svg: std::path::PathBuf,
+    },
+}
+
+fn do_hexes(hex: &str, other_hex: Option<&str>) -> anyhow::Result<()> {
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let graph = hex_graph::HexGraph::new(hex_catalogue.in_hex_grid());
+    let describe = |name: &str| -> anyhow::Result<String> {
+        let info = hex_catalogue.get(name)?;
+        Ok(format!(
+            "{} ({}, at {},{})",
+            info.display_name, info.name, info.grid_coord.0, info.grid_coord.1
+        ))
+    };
+
+    let hex = &hex_catalogue.find(hex)?.name;
+    match other_hex {
+        None => {
+            println!("{} borders:", describe(hex)?);
+            for (neighbour, direction) in graph.neighbours(hex)? {
+                println!("  {:<2} {}", direction, describe(neighbour)?);
+            }
+        }
+        Some(other_hex) => {
+            let other_hex = &hex_catalogue.find(other_hex)?.name;
+            println!(
+                "{} -> {}: {} hexes apart",
+                describe(hex)?,
+                describe(other_hex)?,
+                graph.distance(hex, other_hex)?
+            );
+            match graph.shortest_path(hex, other_hex)? {
+                None => println!("no path between them through the hexes of the grid"),
+                Some(path) => {
+                    println!("shortest path ({} steps):", path.len() - 1);
+                    for name in path {
+                        println!("  {}", describe(name)?);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn do_distance(shard: Shard, from: &str, to: &str) -> anyhow::Result<()> {
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let client = warapi_client(shard, None)?;
+    let maps = load_maps(&client, &hex_catalogue);
+    let world = world::World::new(&hex_catalogue, &maps)?;
+
+    // everything the query could mean, with a description of each
+    let candidates = |query: &str| -> anyhow::Result<Vec<(String, coords::WorldPos)>> {
+        let hex_name = |hex: &str| hex_catalogue.get(hex).map(|h| h.display_name.clone());
+        let mut out = vec![];
+        for place in &world.places {
+            if place.name.to_lowercase() == query.to_lowercase() {
+                out.push((
+                    format!("{} ({})", place.name, hex_name(&place.hex)?),
+                    place.pos,
+                ));
+            }
+        }
+        let icon_types = find_icon_types(query);
+        for item in &world.items {
+            if icon_types.contains(&item.icon_type) {
+                let location = match item.nearest_place() {
+                    Some(place) => place.describe(),
+                    None => format!("in {}", hex_name(&item.hex)?),
+                };
+                out.push((
+                    format!(
+                        "{} {} ({})",
+                        get_icon_display_name(item.icon_type),
+                        location,
+                        team_name(item.team_id)
+                    ),
+                    item.pos,
+                ));
+            }
+        }
+        if out.is_empty() {
+            anyhow::bail!(
+                "nothing on the map called {:?} - neither a location nor an icon type",
+                query
+            );
+        }
+        Ok(out)
+    };
+
+    let from = candidates(from)?;
+    let to = candidates(to)?;
+    let ((from_desc, _), (to_desc, _), distance) = from
+        .iter()
+        .flat_map(|a| to.iter().map(move |b| (a, b, a.1.distance_to(&b.1))))
+        // the same thing on both sides (like when asking for the two closest sulfur mines)
+        .filter(|(_, _, d)| *d > 0.0)
+        .min_by_key(|(_, _, d)| ordered_float::OrderedFloat(*d))
+        .context("nothing to measure between")?;
+    println!(
+        "{} -> {}: {:.0} m ({:.2} hex widths)",
+        from_desc,
+        to_desc,
+        distance,
+        distance / coords::HEX_WIDTH_M
+    );
+    if from.len() * to.len() > 1 {
+        println!("(the closest of {} x {} candidates)", from.len(), to.len());
+    }
+    Ok(())
+}
+
+fn do_items(shard: Shard, hex: Option<&str>, icon_type: Option<&str>) -> anyhow::Result<()> {
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let hex = hex.map(|h| hex_catalogue.find(h)).transpose()?;
+    let icon_types = icon_type.map(find_icon_types);
+    if let (Some(query), Some(icon_types)) = (icon_type, &icon_types) {
+        anyhow::ensure!(!icon_types.is_empty(), "no icon type called {:?}", query);
+    }
+    let client = warapi_client(shard, None)?;
+    let maps = load_maps(&client, &hex_catalogue);
+    let world = world::World::new(&hex_catalogue, &maps)?;
+
+    let mut items = world
+        .items
+        .iter()
+        .filter(|it| hex.is_none_or(|h| h.name == it.hex))
+        .filter(|it| {
+            icon_types
+                .as_ref()
+                .is_none_or(|t| t.contains(&it.icon_type))
+        })
+        .map(|it| Ok((hex_catalogue.get(&it.hex)?.display_name.as_str(), it)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    items.sort_by_key(|(hex_name, it)| {
+        (
+            *hex_name,
+            get_icon_display_name(it.icon_type),
+            ordered_float::OrderedFloat(it.pos.y),
+        )
+    });
+
+    let mut current_hex = None;
+    for (hex_name, it) in items {
+        if current_hex != Some(hex_name) {
+            println!("{}:", hex_name);
+            current_hex = Some(hex_name);
+        }
+        let locations = [&it.nearest_major, &it.nearest_minor]
+            .into_iter()
+            .flatten()
+            .map(|place| place.describe())
+            .collect::<Vec<_>>();
+        println!(
+            "  {:<6} {} ({}) — {}",
+            it.grid_ref,
+            get_icon_display_name(it.icon_type),
+            team_name(it.team_id),
+            locations.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn do_export(shard: Shard, out_dir: &std::path::Path) -> anyhow::Result<()> {
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let client = warapi_client(shard, None)?;
+    let maps = load_maps(&client, &hex_catalogue);
+    let world = world::World::new(&hex_catalogue, &maps)?;
+    export::export_tables(&hex_catalogue, &world, out_dir)?;
+    let wiki_f = &out_dir.join("starting-conditions.wikitext");
+    std::fs::write(
+        wiki_f,
+        wikitext::starting_conditions(&hex_catalogue, &world, &client.war_name)?,
+    )
+    .with_context(|| format!("writing {:?}", wiki_f))?;
+    log::info!("Exported to {}", out_dir.display());
+    Ok(())
+}
+
+fn do_tiles(
+    cfg: &Config,
     shard: Shard,
-    #[bpaf(long)]
-    skip_git: bool,
+    out_dir: &std::path::Path,
+    war: Option<&str>,
+) -> anyhow::Result<()> {
+    check_warapi_repo(cfg)?;
+    let hex_catalogue = hex_catalogue::HexCatalogue::load()?;
+    let client = warapi_client(shard, war)?;
+    let maps = load_maps(&client, &hex_catalogue);
+    let terrain_paths = resolve_terrain_paths(
+        cfg,
+        &hex_catalogue,
+        &maps
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>(),
+    )?;
+    tiles::write_tiles(
+        &cfg.warapi_repo_path,
+        &hex_catalogue,
+        &terrain_paths,
+        &maps,
+        &make_provenance(cfg, &client, &maps),
+        out_dir,
+    )
 }
 
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    let mut cfg = read_config();
     let opts = options().run();
-
+    let mut cfg = read_config();
+    // Where the git repos live, for drawing and for whichever subcommand uses them, so that
+    // they all work off the same checkout whether or not it gets fetched below.
     cfg.warapi_repo_path = "cache/warapi-repo".into();
+    cfg.yino_repo_path = Some("cache/yino-foxhole-web-utils-repo".into());
+
+    // The subcommands fail mostly on typos in what they were given, which deserve
+    // a message rather than a panic
+    let (shard, skip_git) = match opts {
+        Options::Draw { shard, skip_git } => (shard, skip_git),
+        Options::Command(command) => {
+            let result = match command {
+                // doesn't need anything but the datamine
+                Command::Hexes { hex, other_hex } => do_hexes(&hex, other_hex.as_deref()),
+                // the warapi, but none of the git repos
+                Command::Distance { shard, from, to } => do_distance(shard, &from, &to),
+                Command::Items {
+                    shard,
+                    hex,
+                    icon_type,
+                } => do_items(shard, hex.as_deref(), icon_type.as_deref()),
+                Command::Export { shard, out_dir } => do_export(shard, &out_dir),
+                // the warapi repo as it is (the same one the map is drawn from), and maybe not
+                // even the warapi
+                Command::Tiles {
+                    shard,
+                    out_dir,
+                    war,
+                } => do_tiles(&cfg, shard, &out_dir, war.as_deref()),
+                Command::Validate { svg } => validate_svg(&cfg, &svg).map(|ok| {
+                    // the problems are logged already
+                    if !ok {
+                        std::process::exit(1);
+                    }
+                }),
+            };
+            if let Err(e) = result {
+                log::error!("{:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    };
+
     log::info!("initializing warapi repo (for the icons)");
 
-    if !opts.skip_git {
+    if !skip_git {
         fetch_git_repo(
             "https://github.com/clapfoot/warapi.git",
             &cfg.warapi_repo_path,
         );
     }
     log::info!("initializing yinoguns repo (for the historic map data)");
-    cfg.yino_repo_path = Some("cache/yino-foxhole-web-utils-repo".into());
 
-    if !opts.skip_git {
+    if !skip_git {
         fetch_git_repo(
             "https://github.com/clapfoot/warapi.git",
             cfg.yino_repo_path.as_ref().unwrap(),
         );
     }
 
-    do_stuff(&cfg, &opts)
+    if let Err(e) = do_stuff(&cfg, shard) {
+        log::error!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commits `content` as the only file of the repo, on top of whatever HEAD is.
+    fn commit(repo: &git2::Repository, content: &str) -> git2::Oid {
+        let workdir = repo.workdir().unwrap();
+        std::fs::write(workdir.join("file"), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("file")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            content,
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn vector_icon_ids_are_its_own() {
+        let icon_f =
+            std::env::temp_dir().join(format!("mapmaker-test-icon-{}.svg", std::process::id()));
+        std::fs::write(
+            &icon_f,
+            r##"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"
+    xmlns:sodipodi="http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd"
+    xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"
+    width="64" height="32" inkscape:version="1.3">
+  <sodipodi:namedview id="namedview1" pagecolor="#ffffff"/>
+  <metadata><rdf:RDF/></metadata>
+  <defs id="defs1">
+    <linearGradient id="gradient1"><stop offset="0" stop-color="#fff"/></linearGradient>
+  </defs>
+  <path id="path1" inkscape:label="body" style="fill:url(#gradient1)" d="M0 0h64v32z"/>
+  <use xlink:href="#path1" x="2"/>
+</svg>"##,
+        )
+        .unwrap();
+        let (elem, dims) = load_vector_icon(&icon_f, "icon-a", 0.5).unwrap();
+        std::fs::remove_file(&icon_f).unwrap();
+
+        assert_eq!(dims, (32.0, 16.0));
+        let out = elem.to_string();
+        for expected in [
+            r#"<symbol id="icon-a-symbol" viewBox="0 0 64 32">"#,
+            r#"<defs id="icon-a-defs1">"#,
+            r#"<linearGradient id="icon-a-gradient1">"#,
+            r#"style="fill:url(#icon-a-gradient1)""#,
+            r##"<use href="#icon-a-path1" x="2"/>"##,
+            r##"<use height="16" href="#icon-a-symbol" id="icon-a" width="32"/>"##,
+        ] {
+            assert!(out.contains(expected), "no {} in {}", expected, out);
+        }
+        for unexpected in ["namedview", "metadata", "inkscape", "xlink"] {
+            assert!(!out.contains(unexpected), "{} in {}", unexpected, out);
+        }
+    }
+
+    #[test]
+    fn checkout_moves_head_along() {
+        let dir =
+            std::env::temp_dir().join(format!("mapmaker-test-checkout-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let upstream = git2::Repository::init(dir.join("upstream")).unwrap();
+        let first = commit(&upstream, "one");
+        let branch = upstream.head().unwrap().shorthand().unwrap().to_owned();
+
+        let checkout = dir.join("checkout");
+        let repo =
+            git2::Repository::clone(dir.join("upstream").to_str().unwrap(), &checkout).unwrap();
+        assert_eq!(provenance::repo_commit(&checkout), Some(first.to_string()));
+
+        let second = commit(&upstream, "two");
+        repo.find_remote("origin")
+            .unwrap()
+            .fetch(&[&branch], None, None)
+            .unwrap();
+        checkout_fetched(&repo, &branch);
+        assert_eq!(provenance::repo_commit(&checkout), Some(second.to_string()));
+        assert_eq!(
+            std::fs::read_to_string(checkout.join("file")).unwrap(),
+            "two"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
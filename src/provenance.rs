@@ -0,0 +1,114 @@
+//! Where a map came from: which war, which snapshot of it, what made it and from what.
+//! Goes into the svg as Dublin Core metadata, and into the text for the wiki's file page,
+//! so that it doesn't just live in the uploader's head.
+
+use svg::Node;
+use svg::node::element::Element;
+
+pub struct Provenance {
+    pub endpoint: &'static str,
+    pub shard: &'static str,
+    pub war_id: String,
+    pub war_number: i32,
+    /// when the warapi last updated the data we used
+    pub snapshot_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// commit of the warapi repo the icons and terrain came from
+    pub warapi_commit: Option<String>,
+}
+
+const TOOL: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+const WARAPI_REPO_URL: &str = "https://github.com/clapfoot/warapi";
+const RIGHTS: &str = "Map data from the Foxhole War API. Terrain and icons are from the game Foxhole, \
+    © Siege Camp, as published in the War API repository. Used for documentation purposes.";
+
+impl Provenance {
+    pub fn title(&self) -> String {
+        format!(
+            "Foxhole world map, war {} on {}",
+            self.war_number, self.shard
+        )
+    }
+    fn snapshot_time(&self) -> String {
+        self.snapshot_time.map_or("unknown".to_owned(), |t| {
+            t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        })
+    }
+    fn warapi_commit(&self) -> &str {
+        self.warapi_commit.as_deref().unwrap_or("unknown")
+    }
+
+    /// The `<metadata>` element, with an RDF description of the file in Dublin Core terms.
+    pub fn metadata_element(&self) -> Element {
+        let dc = |name: &str, value: String| {
+            let mut e = Element::new(format!("dc:{}", name));
+            e.append(svg::node::Text::new(value));
+            e
+        };
+        let mut description = Element::new("rdf:Description");
+        description.assign("rdf:about", "");
+        for e in [
+            dc("title", self.title()),
+            dc("date", self.snapshot_time()),
+            dc("source", self.endpoint.to_owned()),
+            dc("identifier", format!("war:{}", self.war_id)),
+            dc("creator", TOOL.to_owned()),
+            dc(
+                "description",
+                format!(
+                    "Shard {}, war {} (id {}), War API snapshot of {}. \
+                     Icons and terrain from {} at commit {}.",
+                    self.shard,
+                    self.war_number,
+                    self.war_id,
+                    self.snapshot_time(),
+                    WARAPI_REPO_URL,
+                    self.warapi_commit()
+                ),
+            ),
+            dc("rights", RIGHTS.to_owned()),
+        ] {
+            description.append(e);
+        }
+        let mut rdf = Element::new("rdf:RDF");
+        rdf.assign("xmlns:rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#");
+        rdf.assign("xmlns:dc", "http://purl.org/dc/elements/1.1/");
+        rdf.append(description);
+        let mut metadata = Element::new("metadata");
+        metadata.append(rdf);
+        metadata
+    }
+
+    /// Wikitext for the file's description page on the wiki, saying the same as the metadata.
+    pub fn file_description(&self) -> String {
+        format!(
+            "== Summary ==\n\
+             {}.\n\
+             \n\
+             * Shard: {}\n\
+             * War: {} (id <code>{}</code>)\n\
+             * Data: [{} War API], snapshot of {}\n\
+             * Icons and terrain: [{} warapi repository], commit <code>{}</code>\n\
+             * Made with: {}\n\
+             \n\
+             == Licensing ==\n\
+             {}\n",
+            self.title(),
+            self.shard,
+            self.war_number,
+            self.war_id,
+            self.endpoint,
+            self.snapshot_time(),
+            WARAPI_REPO_URL,
+            self.warapi_commit(),
+            TOOL,
+            RIGHTS
+        )
+    }
+}
+
+/// The commit checked out in the given repo, if it is one.
+pub fn repo_commit(repo_path: &std::path::Path) -> Option<String> {
+    let repo = git2::Repository::open(repo_path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
@@ -0,0 +1,160 @@
+//! Keeps track of how many bytes each part of the generated svg costs us,
+//! because the wiki has an upload limit and we are never too far from it.
+
+use std::collections::BTreeMap;
+
+#[derive(serde::Serialize)]
+pub struct SizeEntry {
+    pub category: &'static str,
+    pub name: String,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+#[derive(serde::Serialize)]
+pub struct CategoryTotal {
+    pub category: &'static str,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+#[derive(serde::Serialize)]
+struct SizeReportJson<'a> {
+    file_bytes: usize,
+    limit_bytes: usize,
+    unaccounted_bytes: usize,
+    categories: Vec<CategoryTotal>,
+    entries: &'a [SizeEntry],
+}
+
+pub struct SizeReport {
+    limit_bytes: usize,
+    entries: Vec<SizeEntry>,
+    // (category, name) -> index into `entries`, so that repeated things
+    // (like icon placements within one hex) get summed up into one line
+    index: BTreeMap<(&'static str, String), usize>,
+}
+impl SizeReport {
+    pub fn new(limit_bytes: usize) -> Self {
+        Self {
+            limit_bytes,
+            entries: vec![],
+            index: BTreeMap::new(),
+        }
+    }
+
+    /// Account for the serialized size of `node` under the given category and name.
+    /// Does not include the whitespace between the elements, that ends up in "other".
+    pub fn add(&mut self, category: &'static str, name: &str, node: &impl std::fmt::Display) {
+        self.add_bytes(category, name, node.to_string().len());
+    }
+
+    pub fn add_bytes(&mut self, category: &'static str, name: &str, bytes: usize) {
+        let key = (category, name.to_owned());
+        match self.index.get(&key) {
+            Some(&i) => {
+                self.entries[i].count += 1;
+                self.entries[i].bytes += bytes;
+            }
+            None => {
+                self.index.insert(key, self.entries.len());
+                self.entries.push(SizeEntry {
+                    category,
+                    name: name.to_owned(),
+                    count: 1,
+                    bytes,
+                });
+            }
+        }
+    }
+
+    fn accounted_bytes(&self) -> usize {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+
+    pub fn category_totals(&self) -> Vec<CategoryTotal> {
+        let mut out: Vec<CategoryTotal> = vec![];
+        for e in &self.entries {
+            match out.iter_mut().find(|c| c.category == e.category) {
+                Some(c) => {
+                    c.count += e.count;
+                    c.bytes += e.bytes;
+                }
+                None => out.push(CategoryTotal {
+                    category: e.category,
+                    count: e.count,
+                    bytes: e.bytes,
+                }),
+            }
+        }
+        out
+    }
+
+    pub fn print_table(&self, file_bytes: usize) {
+        let pct = |bytes: usize| 100.0 * bytes as f64 / file_bytes.max(1) as f64;
+        println!(
+            "{:<16} {:<40} {:>6} {:>12} {:>7}",
+            "category", "name", "count", "bytes", "%"
+        );
+        let totals = self.category_totals();
+        for c in &totals {
+            for e in self.entries.iter().filter(|e| e.category == c.category) {
+                println!(
+                    "{:<16} {:<40} {:>6} {:>12} {:>6.2}%",
+                    e.category,
+                    e.name,
+                    e.count,
+                    e.bytes,
+                    pct(e.bytes)
+                );
+            }
+        }
+        println!();
+        for c in totals {
+            println!(
+                "{:<16} {:<40} {:>6} {:>12} {:>6.2}%",
+                c.category,
+                "(total)",
+                c.count,
+                c.bytes,
+                pct(c.bytes)
+            );
+        }
+        let unaccounted = file_bytes.saturating_sub(self.accounted_bytes());
+        println!(
+            "{:<16} {:<40} {:>6} {:>12} {:>6.2}%",
+            "other",
+            "(whitespace, wrappers, headers)",
+            "",
+            unaccounted,
+            pct(unaccounted)
+        );
+        println!(
+            "{:<16} {:<40} {:>6} {:>12} {:>6.2}% of the {} bytes limit",
+            "file",
+            "(total)",
+            "",
+            file_bytes,
+            100.0 * file_bytes as f64 / self.limit_bytes as f64,
+            self.limit_bytes,
+        );
+        if file_bytes > self.limit_bytes {
+            log::warn!(
+                "output is {} bytes, which is over the configured limit of {} bytes",
+                file_bytes,
+                self.limit_bytes
+            );
+        }
+    }
+
+    pub fn write_json(&self, path: &std::path::Path, file_bytes: usize) {
+        let out = SizeReportJson {
+            file_bytes,
+            limit_bytes: self.limit_bytes,
+            unaccounted_bytes: file_bytes.saturating_sub(self.accounted_bytes()),
+            categories: self.category_totals(),
+            entries: &self.entries,
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&out).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+//! Packs a bunch of icons into a single image, so that we pay the png header
+//! (and the base64 overhead of it) once instead of once per icon, and let the
+//! compressor see all the (very similar) icons at once.
+
+// transparent gap between the sprites, so that the edges don't bleed into each other
+// when the browser samples the sheet at a lower resolution
+const PADDING: u32 = 2;
+
+pub struct SpriteSheet {
+    pub image: image::RgbaImage,
+    /// (x, y, width, height) of each input image within the sheet, in input order
+    pub rects: Vec<(u32, u32, u32, u32)>,
+}
+
+/// Simple shelf packing: tallest images first, left to right, starting a new row
+/// when the current one gets wider than roughly the square root of the total area.
+/// The icons are all about the same size anyway, so nothing fancier is needed.
+pub fn pack(images: &[&image::DynamicImage]) -> SpriteSheet {
+    let total_area: u64 = images
+        .iter()
+        .map(|im| ((im.width() + PADDING) * (im.height() + PADDING)) as u64)
+        .sum();
+    let widest = images.iter().map(|im| im.width()).max().unwrap_or(0);
+    let row_width = widest.max((total_area as f64).sqrt().ceil() as u32);
+
+    let mut order = (0..images.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+    let mut rects = vec![(0, 0, 0, 0); images.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    let mut sheet_width = 0;
+    for i in order {
+        let (w, h) = (images[i].width(), images[i].height());
+        if x > 0 && x + w > row_width {
+            x = 0;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        rects[i] = (x, y, w, h);
+        x += w + PADDING;
+        row_height = row_height.max(h);
+        sheet_width = sheet_width.max(x - PADDING);
+    }
+    let sheet_height = y + row_height;
+
+    let mut image = image::RgbaImage::new(sheet_width.max(1), sheet_height.max(1));
+    for (im, &(x, y, _, _)) in images.iter().zip(&rects) {
+        image::imageops::replace(&mut image, &im.to_rgba8(), x as i64, y as i64);
+    }
+    SpriteSheet { image, rects }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, shade: u8) -> image::DynamicImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba([shade, shade, shade, 255])).into()
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let images = [
+            solid(10, 8, 1),
+            solid(12, 12, 2),
+            solid(10, 10, 3),
+            solid(6, 12, 4),
+            solid(10, 4, 5),
+        ];
+        let sheet = pack(&images.iter().collect::<Vec<_>>());
+        assert_eq!(sheet.rects.len(), images.len());
+        for (i, (im, &(x, y, w, h))) in images.iter().zip(&sheet.rects).enumerate() {
+            // in input order, at their own size, on the sheet, with their own pixels
+            assert_eq!((w, h), (im.width(), im.height()));
+            assert!(x + w <= sheet.image.width() && y + h <= sheet.image.height());
+            for (px, py) in [(x, y), (x + w - 1, y + h - 1)] {
+                assert_eq!(sheet.image.get_pixel(px, py)[0], i as u8 + 1);
+            }
+            // and apart from all the others by at least the padding
+            for &(ox, oy, ow, oh) in &sheet.rects[i + 1..] {
+                let apart = x + w + PADDING <= ox
+                    || ox + ow + PADDING <= x
+                    || y + h + PADDING <= oy
+                    || oy + oh + PADDING <= y;
+                assert!(apart, "{:?} and {:?}", (x, y, w, h), (ox, oy, ow, oh));
+            }
+        }
+        // roughly square, rather than all in one row
+        assert!(sheet.image.width() < 12 + 12 + 10 + 10 + 6);
+    }
+
+    #[test]
+    fn nothing_to_pack() {
+        let sheet = pack(&[]);
+        assert!(sheet.rects.is_empty());
+        assert_eq!(sheet.image.dimensions(), (1, 1));
+    }
+}
@@ -0,0 +1,242 @@
+//! Checks a finished svg against what the wiki will accept for upload, so that we find out
+//! before an editor tries to upload it. Roughly what MediaWiki's upload filter looks for:
+//! scripts and event handlers, references to anything outside of the file itself,
+//! entity declarations and stylesheet instructions, plus the upload size limit.
+
+use std::path::Path;
+
+use anyhow::Context;
+use svg::node::element::tag::Type;
+use svg::parser::Event;
+
+pub struct Problem {
+    /// where in the document, like `svg > defs > g#terrain-group > image#terrain-DeadLandsHex`
+    pub element: String,
+    pub reason: String,
+}
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.element, self.reason)
+    }
+}
+
+/// Elements the wiki refuses outright (or, for `foreignObject`, won't render).
+const FORBIDDEN_ELEMENTS: [&str; 4] = ["script", "handler", "listener", "foreignObject"];
+/// Elements that could swap an href for something else after the fact.
+const ANIMATION_ELEMENTS: [&str; 4] = ["set", "animate", "animateTransform", "animateMotion"];
+/// What embedded images may be. Anything else in a data uri gets rejected.
+const ALLOWED_DATA_URIS: [&str; 3] = ["data:image/png", "data:image/gif", "data:image/jpeg"];
+
+/// Whether a `url(...)` in an attribute or stylesheet points anywhere but into the file.
+fn has_external_url(value: &str) -> bool {
+    value.match_indices("url(").any(|(i, _)| {
+        let target = value[i + 4..].trim_start_matches([' ', '"', '\'']);
+        !target.starts_with('#')
+    })
+}
+
+pub fn check(svg_f: &Path, size_limit_bytes: usize) -> anyhow::Result<Vec<Problem>> {
+    let content = std::fs::read_to_string(svg_f).with_context(|| format!("reading {:?}", svg_f))?;
+    check_content(&content, size_limit_bytes).with_context(|| format!("checking {:?}", svg_f))
+}
+
+fn check_content(content: &str, size_limit_bytes: usize) -> anyhow::Result<Vec<Problem>> {
+    let mut problems = vec![];
+    if content.len() > size_limit_bytes {
+        problems.push(Problem {
+            element: "(file)".to_owned(),
+            reason: format!(
+                "{} bytes, over the limit of {} bytes",
+                content.len(),
+                size_limit_bytes
+            ),
+        });
+    }
+
+    // the open elements, for telling where the problems are
+    let mut stack: Vec<String> = vec![];
+    let here = |stack: &[String], name: &str| {
+        stack
+            .iter()
+            .map(String::as_str)
+            .chain([name])
+            .collect::<Vec<_>>()
+            .join(" > ")
+    };
+    for event in svg::read(content)? {
+        match event {
+            Event::Error(e) => anyhow::bail!("parsing: {}", e),
+            Event::Tag(_, Type::End, _) => {
+                stack.pop();
+            }
+            Event::Tag(name, tag_type, attrs) => {
+                let element = match attrs.get("id") {
+                    Some(id) => format!("{}#{}", name, id),
+                    None => name.to_owned(),
+                };
+                let mut problem = |reason: String| {
+                    problems.push(Problem {
+                        element: here(&stack, &element),
+                        reason,
+                    })
+                };
+
+                if FORBIDDEN_ELEMENTS.contains(&name) {
+                    problem(format!("<{}> is not allowed", name));
+                }
+                // sorted, so the problems come out in the same order every time
+                let mut attrs = attrs.into_iter().collect::<Vec<_>>();
+                attrs.sort_by(|a, b| a.0.cmp(&b.0));
+                for (attr, value) in attrs {
+                    let value = value.to_string();
+                    if attr.to_lowercase().starts_with("on") {
+                        problem(format!("event handler attribute {}", attr));
+                    } else if attr == "href" || attr == "xlink:href" {
+                        let allowed = value.starts_with('#')
+                            || (["image", "feImage"].contains(&name)
+                                && ALLOWED_DATA_URIS.iter().any(|d| value.starts_with(d)));
+                        if !allowed {
+                            // no need to print all of a huge data uri
+                            let shown = value.chars().take(60).collect::<String>();
+                            problem(format!("{} points outside of the file: {:?}", attr, shown));
+                        }
+                    } else if ANIMATION_ELEMENTS.contains(&name)
+                        && attr == "attributeName"
+                        && value.ends_with("href")
+                    {
+                        problem(format!("<{}> animating {}", name, value));
+                    } else if has_external_url(&value) {
+                        problem(format!("{} references something outside of the file", attr));
+                    } else if attr == "style" && value.contains("@import") {
+                        problem("style imports another stylesheet".to_owned());
+                    }
+                }
+
+                if tag_type == Type::Start {
+                    stack.push(element);
+                }
+            }
+            Event::Text(text) => {
+                if stack
+                    .last()
+                    .is_some_and(|e| e == "style" || e.starts_with("style#"))
+                    && (text.contains("@import") || has_external_url(text))
+                {
+                    problems.push(Problem {
+                        element: here(&stack[..stack.len() - 1], stack.last().unwrap()),
+                        reason: "stylesheet references something outside of the file".to_owned(),
+                    });
+                }
+            }
+            Event::Declaration(declaration) => {
+                if declaration.contains("ENTITY") {
+                    problems.push(Problem {
+                        element: "(prolog)".to_owned(),
+                        reason: "entity declarations are not allowed".to_owned(),
+                    });
+                }
+            }
+            Event::Instruction(instruction) => {
+                if !instruction.trim_start_matches("<?").starts_with("xml ") {
+                    problems.push(Problem {
+                        element: "(prolog)".to_owned(),
+                        reason: format!("processing instruction {:?}", instruction),
+                    });
+                }
+            }
+            Event::Comment(_) => {}
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(body: &str) -> Vec<String> {
+        let content = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><svg xmlns="http://www.w3.org/2000/svg">{}</svg>"#,
+            body
+        );
+        check_content(&content, 10_000)
+            .unwrap()
+            .iter()
+            .map(Problem::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn fine() {
+        let body = r##"<defs><linearGradient id="g"/></defs>
+            <g id="a" style="fill:url(#g)"><use href="#g" x="1"/></g>
+            <image href="data:image/png;base64,AAAA"/><image href="data:image/jpeg;base64,AAAA"/>"##;
+        assert_eq!(problems(body), Vec::<String>::new());
+    }
+
+    #[test]
+    fn too_big() {
+        let content = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">{}</svg>"#,
+            " ".repeat(100)
+        );
+        let problems = check_content(&content, 100).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].element, "(file)");
+    }
+
+    #[test]
+    fn scripts_and_handlers() {
+        assert_eq!(
+            problems(r#"<g id="a"><script>alert(1)</script><rect onClick="alert(1)"/></g>"#),
+            [
+                "svg > g#a > script: <script> is not allowed",
+                "svg > g#a > rect: event handler attribute onClick",
+            ]
+        );
+    }
+
+    #[test]
+    fn outside_references() {
+        assert_eq!(
+            problems(
+                r#"<image href="https://example.com/a.png"/>
+                <image href="data:image/webp;base64,AAAA"/>
+                <image href="data:image/svg+xml;base64,AAAA"/>
+                <use href="data:image/png;base64,AAAA"/>
+                <rect fill="url(https://example.com/a.svg#g)"/>
+                <animate attributeName="href" to="https://example.com"/>"#
+            ),
+            [
+                r#"svg > image: href points outside of the file: "https://example.com/a.png""#,
+                r#"svg > image: href points outside of the file: "data:image/webp;base64,AAAA""#,
+                r#"svg > image: href points outside of the file: "data:image/svg+xml;base64,AAAA""#,
+                r#"svg > use: href points outside of the file: "data:image/png;base64,AAAA""#,
+                "svg > rect: fill references something outside of the file",
+                "svg > animate: <animate> animating href",
+            ]
+        );
+    }
+
+    #[test]
+    fn stylesheets() {
+        assert_eq!(
+            problems(
+                r#"<style>@import "https://example.com/a.css";</style>
+                <rect style="fill:red;@import x"/>"#
+            ),
+            [
+                "svg > style: stylesheet references something outside of the file",
+                "svg > rect: style imports another stylesheet",
+            ]
+        );
+    }
+
+    #[test]
+    fn entities() {
+        let content = r#"<?xml version="1.0"?><!DOCTYPE svg [<!ENTITY a "b">]><svg xmlns="http://www.w3.org/2000/svg"/>"#;
+        let problems = check_content(content, 10_000).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].element, "(prolog)");
+    }
+}
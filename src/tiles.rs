@@ -0,0 +1,284 @@
+//! The map as a z/x/y pyramid of png tiles, for Leaflet and friends. Unlike the svg, this
+//! has the terrain at the full resolution of the TGAs, as the browser only ever loads the
+//! tiles it shows.
+//!
+//! Everything is done on the CPU with the `image` crate: the whole world gets composed at
+//! full resolution in one go (terrain, then the icons on top, colored like the svg filters
+//! color them), cut into the tiles of the deepest zoom level, and then halved for every
+//! level above it.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::Path;
+
+use anyhow::Context;
+use image::{GenericImageView, Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::coords;
+use crate::hex_catalogue::HexCatalogue;
+use crate::provenance::Provenance;
+use crate::warapi_schema;
+
+pub const TILE_SIZE: u32 = 256;
+
+#[derive(Serialize)]
+struct Manifest {
+    title: String,
+    /// when the warapi last updated the data the icons come from
+    snapshot_time: Option<String>,
+    url_template: &'static str,
+    tile_size: u32,
+    min_zoom: u32,
+    max_zoom: u32,
+    /// size of the whole map at `max_zoom`, the tiles cover a square from the top left
+    width: u32,
+    height: u32,
+    /// at `max_zoom`
+    px_per_metre: f32,
+    /// where the world origin (the centre of Deadlands) is at `max_zoom`,
+    /// for going from world coordinates to pixels
+    origin_px: (f32, f32),
+}
+
+/// Whether a point, relative to the top left of the bounding box of a hex that is
+/// `width` wide, is inside of the hex. Same shape as `coords::hex_polygon_points`.
+fn in_hex(x: f32, y: f32, width: f32) -> bool {
+    let radius_long = width / 2.0;
+    let (dx, dy) = (
+        (x - radius_long).abs(),
+        (y - radius_long * coords::COS_30).abs(),
+    );
+    dy <= radius_long * coords::COS_30
+        && coords::COS_30 * dx + coords::SIN_30 * dy <= radius_long * coords::COS_30
+}
+
+/// Multiplies the color onto the icon, like the `feColorMatrix` of the svg does.
+fn colorize(icon: &mut RgbaImage, color: [u8; 3]) {
+    for p in icon.pixels_mut() {
+        for c in 0..3 {
+            p[c] = ((p[c] as u32 * color[c] as u32 + 127) / 255) as u8;
+        }
+    }
+}
+
+/// The TGA of the icon, scaled and colored.
+fn load_icon(
+    warapi_repo_path: &Path,
+    mi: &warapi_schema::MapItem,
+    scale: f32,
+) -> anyhow::Result<RgbaImage> {
+    let icon_f = &warapi_repo_path
+        .join("Images")
+        .join("MapIcons")
+        .join(format!(
+            "MapIcon{}.TGA",
+            crate::get_icon_file_name(mi.icon_type)
+        ));
+    let icon = image::ImageReader::open(icon_f)
+        .with_context(|| format!("trying to open {:?} - icon type {}", icon_f, mi.icon_type))?
+        .decode()
+        .with_context(|| format!("decoding {:?}", icon_f))?;
+    let mut icon = icon
+        .resize(
+            (icon.width() as f32 * scale).round() as u32,
+            (icon.height() as f32 * scale).round() as u32,
+            image::imageops::FilterType::Lanczos3,
+        )
+        .into_rgba8();
+    if let Some((_, color)) = crate::icon_color(mi.icon_type, mi.team_id) {
+        colorize(&mut icon, *color);
+    }
+    Ok(icon)
+}
+
+/// Half the size, each pixel the average of the four it replaces. Weighted by alpha, so
+/// that the transparent (black) pixels around the world don't darken its edges.
+fn halve(img: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(img.width().div_ceil(2), img.height().div_ceil(2), |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            if let Some(p) = img.get_pixel_checked(x * 2 + dx, y * 2 + dy) {
+                for c in 0..3 {
+                    sum[c] += p[c] as u32 * p[3] as u32;
+                }
+                sum[3] += p[3] as u32;
+            }
+        }
+        if sum[3] == 0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        Rgba([
+            (sum[0] / sum[3]) as u8,
+            (sum[1] / sum[3]) as u8,
+            (sum[2] / sum[3]) as u8,
+            (sum[3] / 4) as u8,
+        ])
+    })
+}
+
+/// Cuts one zoom level into tiles. The ones with nothing on them are left out.
+fn write_level(img: &RgbaImage, z: u32, out_dir: &Path) -> anyhow::Result<usize> {
+    let mut written = 0;
+    for tx in 0..img.width().div_ceil(TILE_SIZE) {
+        let dir = out_dir.join(z.to_string()).join(tx.to_string());
+        for ty in 0..img.height().div_ceil(TILE_SIZE) {
+            let (x, y) = (tx * TILE_SIZE, ty * TILE_SIZE);
+            let (w, h) = (
+                TILE_SIZE.min(img.width() - x),
+                TILE_SIZE.min(img.height() - y),
+            );
+            let view = image::imageops::crop_imm(img, x, y, w, h);
+            if view.pixels().all(|(_, _, p)| p[3] == 0) {
+                continue;
+            }
+            let mut tile = RgbaImage::new(TILE_SIZE, TILE_SIZE);
+            image::imageops::replace(&mut tile, &*view, 0, 0);
+            std::fs::create_dir_all(&dir).with_context(|| format!("creating {:?}", dir))?;
+            let tile_f = dir.join(format!("{}.png", ty));
+            tile.save(&tile_f)
+                .with_context(|| format!("writing {:?}", tile_f))?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+pub fn write_tiles(
+    warapi_repo_path: &Path,
+    hex_catalogue: &HexCatalogue,
+    terrain_paths: &std::collections::BTreeMap<String, std::path::PathBuf>,
+    maps: &[(String, warapi_schema::Map)],
+    provenance: &Provenance,
+    out_dir: &Path,
+) -> anyhow::Result<()> {
+    // one pixel of terrain is one pixel of the deepest zoom level. All the TGAs are the same
+    // size, so the first one says how big a hex is.
+    let (terrain_width, terrain_height) = {
+        let first = terrain_paths.values().next().context("no hexes to draw")?;
+        image::image_dimensions(first).with_context(|| format!("reading {:?}", first))?
+    };
+    let layout = coords::CanvasTransform::for_hex_width(terrain_width as f32);
+
+    // the bounding box of all the hexes, which is where the map starts
+    let hex_top_lefts = maps
+        .iter()
+        .map(|(map_name, _)| {
+            let grid_coord = hex_catalogue.get(map_name)?.grid_coord;
+            Ok(layout.to_canvas(coords::hex_top_left(grid_coord)))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let hex_height_px = coords::HEX_HEIGHT_M * layout.px_per_metre;
+    let (mut xmin, mut ymin, mut xmax, mut ymax) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in &hex_top_lefts {
+        xmin = xmin.min(x);
+        ymin = ymin.min(y);
+        xmax = xmax.max(x + terrain_width as f32);
+        ymax = ymax.max(y + hex_height_px);
+    }
+    let layout = layout.shifted((-xmin.floor(), -ymin.floor()));
+    let (width, height) = (
+        (xmax - xmin.floor()).ceil() as u32,
+        (ymax - ymin.floor()).ceil() as u32,
+    );
+    log::info!(
+        "composing the world at {}x{} px, that's {} MB",
+        width,
+        height,
+        width as usize * height as usize * 4 / 1024 / 1024
+    );
+    let mut world = RgbaImage::new(width, height);
+
+    // the terrain, cut to the hexagon. Which pixel belongs to which hex is decided in
+    // unrounded world pixels, so that the hexes fit together without gaps.
+    for (map_name, _) in maps {
+        log::info!("terrain of {}", map_name);
+        let terrain_f = &terrain_paths[map_name];
+        let terrain = image::ImageReader::open(terrain_f)
+            .with_context(|| format!("reading {:?}", terrain_f))?
+            .decode()
+            .with_context(|| format!("decoding {:?}", terrain_f))?
+            .into_rgba8();
+        anyhow::ensure!(
+            terrain.dimensions() == (terrain_width, terrain_height),
+            "{:?} is {:?}, not {}x{} like the others",
+            terrain_f,
+            terrain.dimensions(),
+            terrain_width,
+            terrain_height
+        );
+        let (left, top) = layout.to_canvas(coords::hex_top_left(
+            hex_catalogue.get(map_name)?.grid_coord,
+        ));
+        for y in top.floor() as u32..((top + hex_height_px).ceil() as u32).min(height) {
+            for x in left.floor() as u32..((left + terrain_width as f32).ceil() as u32).min(width) {
+                let (lx, ly) = (x as f32 + 0.5 - left, y as f32 + 0.5 - top);
+                if in_hex(lx, ly, terrain_width as f32) {
+                    let p = terrain.get_pixel(
+                        (lx as u32).min(terrain_width - 1),
+                        (ly as u32).min(terrain_height - 1),
+                    );
+                    world.put_pixel(x, y, *p);
+                }
+            }
+        }
+    }
+
+    // The icons, on top of all of the terrain, at the same size relative to it as in the svg.
+    // Always the TGAs, as we've got nothing to draw the vector ones with.
+    let icon_scale = crate::ICON_SCALE_FACTOR / crate::TERRAIN_RESIZE_FACTOR;
+    let mut icons: HashMap<(i32, warapi_schema::TeamId), RgbaImage> = HashMap::new();
+    for (map_name, map) in maps {
+        let grid_coord = hex_catalogue.get(map_name)?.grid_coord;
+        // top-to-bottom, like the svg
+        let mut map_items = map.map_items.iter().collect::<Vec<_>>();
+        map_items.sort_by_key(|mi| ordered_float::OrderedFloat(mi.y));
+        for mi in map_items {
+            let key = (mi.icon_type, mi.team_id);
+            let icon = match icons.entry(key) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(load_icon(warapi_repo_path, mi, icon_scale)?),
+            };
+            let (x, y) = layout.to_canvas(coords::hex_local_to_world(grid_coord, mi.x, mi.y));
+            let (x, y) = (
+                x - icon.width() as f32 / 2.0,
+                y - icon.height() as f32 / 2.0,
+            );
+            image::imageops::overlay(&mut world, icon, x.round() as i64, y.round() as i64);
+        }
+    }
+
+    // zoom 0 is the whole map in one tile
+    let max_zoom = (width.max(height) as f32 / TILE_SIZE as f32)
+        .log2()
+        .ceil()
+        .max(0.0) as u32;
+    let mut level = world;
+    for z in (0..=max_zoom).rev() {
+        let written = write_level(&level, z, out_dir)?;
+        log::info!("zoom {}: {} tiles", z, written);
+        if z > 0 {
+            level = halve(&level);
+        }
+    }
+
+    let manifest = Manifest {
+        title: provenance.title(),
+        snapshot_time: provenance
+            .snapshot_time
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        url_template: "{z}/{x}/{y}.png",
+        tile_size: TILE_SIZE,
+        min_zoom: 0,
+        max_zoom,
+        width,
+        height,
+        px_per_metre: layout.px_per_metre,
+        origin_px: layout.origin_px,
+    };
+    let manifest_f = &out_dir.join("manifest.json");
+    std::fs::write(manifest_f, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("writing {:?}", manifest_f))?;
+    log::info!("Tiles written to {}", out_dir.display());
+    Ok(())
+}
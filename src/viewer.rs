@@ -0,0 +1,67 @@
+//! A single html page for looking at the map in a browser: the svg, inlined, plus a bit of
+//! script for pan and zoom, tooltips, layer toggles and finding towns.
+//! The tooltips come from a list of the icons and where they are, so that none of it has to
+//! be in the svg that goes on the wiki. The page itself is in `viewer.html`.
+
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Serialize;
+
+const TEMPLATE: &str = include_str!("viewer.html");
+
+/// A named location for the search, in svg pixels.
+#[derive(Serialize)]
+pub struct ViewerPlace<'a> {
+    pub name: &'a str,
+    pub hex: &'a str,
+    pub major: bool,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// An icon, for the tooltips: what it is, and its bounding box in svg pixels.
+#[derive(Serialize)]
+pub struct ViewerItem<'a> {
+    pub name: &'static str,
+    pub team: &'static str,
+    pub hex: &'a str,
+    pub grid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<String>,
+    /// how many icons this one stands for, if it's more than one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn write_html(
+    html_f: &Path,
+    svg_content: &str,
+    title: &str,
+    places: &[ViewerPlace],
+    items: &[ViewerItem],
+) -> anyhow::Result<()> {
+    // goes into a <script>, which must not get closed early by anything in the names
+    let places_json = serde_json::to_string(places)?.replace("</", "<\\/");
+    let items_json = serde_json::to_string(items)?.replace("</", "<\\/");
+    let html = TEMPLATE
+        .replace("{{TITLE}}", &escape_html(title))
+        .replace("{{PLACES}}", &places_json)
+        .replace("{{ITEMS}}", &items_json)
+        // last, as the svg is big, and has no business being searched for placeholders
+        .replace("{{SVG}}", svg_content);
+    std::fs::write(html_f, html).with_context(|| format!("writing {:?}", html_f))
+}
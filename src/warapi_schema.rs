@@ -17,8 +17,9 @@ pub struct Map {
     pub map_items: Vec<MapItem>,
     // pub map_items_c: Vec<MapItem>,
     // pub map_items_w: Vec<MapItem>,
-    #[allow(unused)]
     pub map_text_items: Vec<MapTextItem>,
+    /// unix millis
+    pub last_updated: i64,
 }
 
 #[derive(Deserialize, Clone)]
@@ -28,11 +29,32 @@ pub struct MapItem {
     pub icon_type: i32,
     pub x: f32,
     pub y: f32,
-    // pub flags: i32,
+    pub flags: i32,
     // view_direction: i32,
 }
 
-#[derive(Deserialize, PartialEq, Eq, Clone, Copy)]
+/// Names of the bits set in `MapItem::flags`.
+/// source: https://github.com/clapfoot/warapi?tab=readme-ov-file#map-flags
+/// Bits that aren't documented there come out as hex.
+pub fn flag_names(flags: i32) -> Vec<String> {
+    const FLAGS: [(i32, &str); 5] = [
+        (0x01, "IsVictoryBase"),
+        (0x02, "IsHomeBase"),
+        (0x04, "IsBuildSite"),
+        (0x10, "IsScorched"),
+        (0x20, "IsTownClaimed"),
+    ];
+    (0..31)
+        .map(|bit| 1 << bit)
+        .filter(|bit| flags & bit != 0)
+        .map(|bit| match FLAGS.iter().find(|(b, _)| *b == bit) {
+            Some((_, name)) => name.to_string(),
+            None => format!("{:#04x}", bit),
+        })
+        .collect()
+}
+
+#[derive(Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TeamId {
     #[serde(rename = "NONE")]
     Nobody,
@@ -44,7 +66,6 @@ pub enum TeamId {
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[allow(unused)]
 pub struct MapTextItem {
     pub text: String,
     pub x: f32,
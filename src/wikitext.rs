@@ -0,0 +1,295 @@
+//! Wikitext for the wiki: the tables for the starting conditions pages, so they don't have
+//! to be typed by hand anymore, and the clickable map. Sorted throughout, and without any
+//! timestamps, so that the outputs of two wars can be diffed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::export::CountRow;
+use crate::hex_catalogue::HexCatalogue;
+use crate::world::World;
+
+/// The icon types that count as resources, in the order of the table columns.
+/// Fields first, then the mines of the same resource.
+const RESOURCE_ICON_FILES: [&str; 10] = [
+    "Salvage",
+    "SalvageMine",
+    "Components",
+    "ComponentMine",
+    "Sulfur",
+    "SulfurMine",
+    "Coal",
+    "OilWell",
+    "FuelField",
+    "FacilityMineOilRig",
+];
+
+fn is_resource(row: &CountRow) -> bool {
+    RESOURCE_ICON_FILES.contains(&row.icon_file)
+}
+
+/// "Salvage Field ×3, Salvage Mine" and so on
+fn list_counts<'a>(counts: impl IntoIterator<Item = (&'a str, usize)>) -> String {
+    let list = counts
+        .into_iter()
+        .map(|(icon, count)| match count {
+            1 => icon.to_owned(),
+            n => format!("{} ×{}", icon, n),
+        })
+        .collect::<Vec<_>>();
+    match list.is_empty() {
+        true => "none".to_owned(),
+        false => list.join(", "),
+    }
+}
+
+/// Everything for the page: the resources table, the per-faction structure counts,
+/// and an infobox per hex. `snapshot` says which war the data is from, like `able-128`.
+pub fn starting_conditions(
+    hex_catalogue: &HexCatalogue,
+    world: &World,
+    snapshot: &str,
+) -> anyhow::Result<String> {
+    let counts = crate::export::count_rows(hex_catalogue, world)?;
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<!-- generated by mapmaker from the {} War API snapshot, edits will be overwritten -->",
+        snapshot
+    )?;
+
+    // resources per hex. Only the columns that have anything in them.
+    {
+        let columns = RESOURCE_ICON_FILES
+            .iter()
+            .filter_map(|file| counts.iter().find(|r| r.icon_file == *file))
+            .map(|r| r.icon)
+            .collect::<Vec<_>>();
+        let mut per_hex: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+        for row in counts.iter().filter(|r| is_resource(r)) {
+            *per_hex
+                .entry(row.hex_name)
+                .or_default()
+                .entry(row.icon)
+                .or_default() += row.count;
+        }
+
+        writeln!(out, "== Resources ==")?;
+        writeln!(out, "{{| class=\"wikitable sortable\"")?;
+        writeln!(out, "! Region !! {}", columns.join(" !! "))?;
+        for (hex_name, resources) in &per_hex {
+            let cells = columns
+                .iter()
+                .map(|icon| resources.get(icon).copied().unwrap_or(0).to_string())
+                .collect::<Vec<_>>();
+            writeln!(out, "|-")?;
+            writeln!(out, "| [[{}]] || {}", hex_name, cells.join(" || "))?;
+        }
+        let totals = columns
+            .iter()
+            .map(|icon| {
+                per_hex
+                    .values()
+                    .filter_map(|r| r.get(icon))
+                    .sum::<usize>()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "|- class=\"sortbottom\"")?;
+        writeln!(out, "! Total !! {}", totals.join(" !! "))?;
+        writeln!(out, "|}}")?;
+        writeln!(out)?;
+    }
+
+    // what each faction starts with, over the whole map
+    {
+        let mut per_structure: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for row in counts.iter().filter(|r| !is_resource(r)) {
+            let entry = per_structure.entry(row.icon).or_default();
+            match row.team {
+                "Colonials" => entry.0 += row.count,
+                "Wardens" => entry.1 += row.count,
+                _ => {}
+            }
+        }
+        per_structure.retain(|_, (colonial, warden)| *colonial + *warden > 0);
+
+        writeln!(out, "== Starting structures ==")?;
+        writeln!(out, "{{| class=\"wikitable sortable\"")?;
+        writeln!(out, "! Structure !! Colonials !! Wardens")?;
+        for (icon, (colonial, warden)) in &per_structure {
+            writeln!(out, "|-")?;
+            writeln!(out, "| {} || {} || {}", icon, colonial, warden)?;
+        }
+        let (colonial, warden) = per_structure
+            .values()
+            .fold((0, 0), |(c, w), (dc, dw)| (c + dc, w + dw));
+        writeln!(out, "|- class=\"sortbottom\"")?;
+        writeln!(out, "! Total !! {} !! {}", colonial, warden)?;
+        writeln!(out, "|}}")?;
+        writeln!(out)?;
+    }
+
+    // and an infobox for every hex
+    {
+        let mut towns: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for place in world.places.iter().filter(|p| p.major) {
+            towns
+                .entry(hex_catalogue.get(&place.hex)?.display_name.as_str())
+                .or_default()
+                .insert(&place.name);
+        }
+        let mut hexes = world
+            .hexes
+            .iter()
+            .map(|hex| hex_catalogue.get(hex))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        hexes.sort_by_key(|h| &h.display_name);
+
+        writeln!(out, "== Regions ==")?;
+        for hex in hexes {
+            let hex_counts = counts
+                .iter()
+                .filter(|r| r.hex == hex.name)
+                .collect::<Vec<_>>();
+            let of_team = |team: &str| {
+                list_counts(
+                    hex_counts
+                        .iter()
+                        .filter(|r| !is_resource(r) && r.team == team)
+                        .map(|r| (r.icon, r.count)),
+                )
+            };
+            // a hex might have the same resource under several teams (a mine someone built on)
+            let mut resources: BTreeMap<&str, usize> = BTreeMap::new();
+            for r in hex_counts.iter().filter(|r| is_resource(r)) {
+                *resources.entry(r.icon).or_default() += r.count;
+            }
+            let hex_towns = towns
+                .get(hex.display_name.as_str())
+                .map(|t| t.iter().copied().collect::<Vec<_>>().join(", "))
+                .unwrap_or_else(|| "none".to_owned());
+
+            writeln!(out, "=== {} ===", hex.display_name)?;
+            writeln!(out, "{{| class=\"wikitable\" style=\"float:right\"")?;
+            writeln!(out, "! colspan=\"2\" | [[{}]]", hex.display_name)?;
+            for (label, value) in [
+                (
+                    "Grid coordinate",
+                    format!("{}, {}", hex.grid_coord.0, hex.grid_coord.1),
+                ),
+                ("Towns", hex_towns),
+                ("Colonial structures", of_team("Colonials")),
+                ("Warden structures", of_team("Wardens")),
+                ("Resources", list_counts(resources)),
+            ] {
+                writeln!(out, "|-")?;
+                writeln!(out, "! {}", label)?;
+                writeln!(out, "| {}", value)?;
+            }
+            writeln!(out, "|}}")?;
+            writeln!(out, "{{{{clear}}}}")?;
+            writeln!(out)?;
+        }
+    }
+    Ok(out)
+}
+
+pub struct ImagemapHex<'a> {
+    /// the wiki page it links to
+    pub page: &'a str,
+    /// what the link says
+    pub title: &'a str,
+    /// in svg pixels
+    pub corners: Vec<(f32, f32)>,
+}
+
+/// An `<imagemap>` for the map, with each hex linking to its page.
+pub fn imagemap(file_name: &str, hexes: &[ImagemapHex]) -> String {
+    let mut out = String::new();
+    out += "<imagemap>\n";
+    // the width is just a starting point, the coordinates below scale with it
+    out += &format!("File:{}|1000px|Map of the world\n", file_name);
+    let mut hexes = hexes.iter().collect::<Vec<_>>();
+    hexes.sort_by_key(|h| h.page);
+    for ImagemapHex {
+        page,
+        title,
+        corners,
+    } in hexes
+    {
+        let points = corners
+            .iter()
+            .map(|(x, y)| format!("{} {}", x.round(), y.round()))
+            .collect::<Vec<_>>();
+        let link = match page == title {
+            true => format!("[[{}]]", page),
+            false => format!("[[{}|{}]]", page, title),
+        };
+        out += &format!("poly {} {}\n", points.join(" "), link);
+    }
+    out += "desc none\n";
+    out += "</imagemap>\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(maps: &[(String, crate::warapi_schema::Map)]) -> String {
+        let hex_catalogue = HexCatalogue::load().unwrap();
+        let world = World::new(&hex_catalogue, maps).unwrap();
+        starting_conditions(&hex_catalogue, &world, "able-1").unwrap()
+    }
+
+    #[test]
+    fn sorted_whatever_the_order_of_the_maps() {
+        let mut maps = crate::world::sample_maps();
+        let out = page(&maps);
+        maps.reverse();
+        for (_, map) in &mut maps {
+            map.map_items.reverse();
+        }
+        assert_eq!(page(&maps), out);
+
+        let position = |s: &str| out.find(s).unwrap();
+        assert!(position("| [[Callahan's Passage]] ||") < position("| [[Deadlands]] ||"));
+        assert!(position("| Hospital ||") < position("| Refinery ||"));
+        assert!(position("=== Callahan's Passage ===") < position("=== Deadlands ==="));
+    }
+
+    #[test]
+    fn only_the_resources_there_are() {
+        let out = page(&crate::world::sample_maps());
+        assert!(out.contains(
+            "! Region !! Salvage Field !! Salvage Mine !! Coal Field\n\
+             |-\n\
+             | [[Callahan's Passage]] || 0 || 0 || 1\n\
+             |-\n\
+             | [[Deadlands]] || 2 || 1 || 0\n\
+             |- class=\"sortbottom\"\n\
+             ! Total !! 2 !! 1 !! 1\n"
+        ));
+        // structures are counted per team, and resources aren't structures
+        assert!(out.contains("| Town Base 3 || 1 || 1\n"));
+        assert!(out.contains("! Total !! 2 !! 2\n"));
+    }
+
+    #[test]
+    fn infobox_splits_teams_and_resources() {
+        let out = page(&crate::world::sample_maps());
+        let deadlands = &out[out.find("=== Deadlands ===").unwrap()..];
+        for (label, value) in [
+            ("Grid coordinate", "0, 0"),
+            ("Towns", "Brine Glen"),
+            ("Colonial structures", "Hospital, Town Base 3"),
+            // the warden salvage mine is a resource
+            ("Warden structures", "none"),
+            ("Resources", "Salvage Field ×2, Salvage Mine"),
+        ] {
+            let row = format!("! {}\n| {}\n", label, value);
+            assert!(deadlands.contains(&row), "no {:?} in {}", row, deadlands);
+        }
+    }
+}
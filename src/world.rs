@@ -0,0 +1,226 @@
+//! Everything on the map at once, in world coordinates, rather than hex by hex.
+//! For the questions that don't care about hex borders, like how far the nearest sulfur is.
+
+use std::collections::HashMap;
+
+use crate::coords::{self, WorldPos};
+use crate::hex_catalogue::HexCatalogue;
+use crate::warapi_schema::{Map, TeamId};
+
+pub struct Item {
+    /// the hex it is in, like `DeadLandsHex`
+    pub hex: String,
+    pub icon_type: i32,
+    pub team_id: TeamId,
+    pub flags: i32,
+    pub pos: WorldPos,
+    /// in-game grid reference within its hex, like "G7k3"
+    pub grid_ref: String,
+    /// The closest town (or other Major location) and the closest Minor location.
+    /// From the whole world, as the closest one might well be across the hex border.
+    pub nearest_major: Option<NearbyPlace>,
+    pub nearest_minor: Option<NearbyPlace>,
+}
+
+/// A named location, from the map text items.
+pub struct Place {
+    pub hex: String,
+    pub name: String,
+    /// towns and such are Major, the smaller spots in between are Minor
+    pub major: bool,
+    pub pos: WorldPos,
+}
+
+/// Where something is relative to a named location.
+#[derive(Clone)]
+pub struct NearbyPlace {
+    pub name: String,
+    pub distance_m: f32,
+    /// 8-point compass direction from the place to the thing
+    pub direction: &'static str,
+}
+impl NearbyPlace {
+    /// The way the wiki puts it, e.g. "120 m NE of Brine Glen". Or "at Brine Glen" for
+    /// what's right on the spot, like the town's own base, which has no direction to speak of.
+    pub fn describe(&self) -> String {
+        if self.distance_m < 5.0 {
+            return format!("at {}", self.name);
+        }
+        format!(
+            "{} {} of {}",
+            format_distance(self.distance_m),
+            self.direction,
+            self.name
+        )
+    }
+}
+
+/// Metres to the nearest ten, or kilometres with one decimal once it's that far.
+/// Nobody cares about the exact metre, and the positions aren't that precise anyway.
+pub fn format_distance(distance_m: f32) -> String {
+    // rounded first, or 996 m would come out as "1000 m"
+    let rounded_m = (distance_m / 10.0).round() * 10.0;
+    if rounded_m < 1000.0 {
+        format!("{} m", rounded_m)
+    } else {
+        format!("{:.1} km", distance_m / 1000.0)
+    }
+}
+
+pub struct World {
+    /// the hexes the maps were for, like `DeadLandsHex`
+    pub hexes: Vec<String>,
+    pub items: Vec<Item>,
+    pub places: Vec<Place>,
+    /// index into `items` by the hex and the index in that hex's `map_items`
+    item_index: HashMap<(String, usize), usize>,
+}
+impl World {
+    pub fn new(hex_catalogue: &HexCatalogue, maps: &[(String, Map)]) -> anyhow::Result<Self> {
+        let mut places = vec![];
+        for (map_name, map) in maps {
+            let grid_coord = hex_catalogue.get(map_name)?.grid_coord;
+            places.extend(map.map_text_items.iter().map(|mti| Place {
+                hex: map_name.clone(),
+                name: mti.text.clone(),
+                major: mti.map_marker_type == "Major",
+                pos: coords::hex_local_to_world(grid_coord, mti.x, mti.y),
+            }));
+        }
+        // all the places first, so that the items can look for them over the hex borders
+        let mut items = vec![];
+        let mut item_index = HashMap::new();
+        for (map_name, map) in maps {
+            let grid_coord = hex_catalogue.get(map_name)?.grid_coord;
+            for (i, mi) in map.map_items.iter().enumerate() {
+                let pos = coords::hex_local_to_world(grid_coord, mi.x, mi.y);
+                item_index.insert((map_name.clone(), i), items.len());
+                items.push(Item {
+                    hex: map_name.clone(),
+                    icon_type: mi.icon_type,
+                    team_id: mi.team_id,
+                    flags: mi.flags,
+                    pos,
+                    grid_ref: coords::grid_reference(mi.x, mi.y),
+                    nearest_major: nearest_place(&places, pos, true),
+                    nearest_minor: nearest_place(&places, pos, false),
+                });
+            }
+        }
+        let hexes = maps.iter().map(|(map_name, _)| map_name.clone()).collect();
+        Ok(Self {
+            hexes,
+            items,
+            places,
+            item_index,
+        })
+    }
+
+    /// The item for one of the `map_items` of a hex, by its index there.
+    pub fn item(&self, hex: &str, map_item_index: usize) -> Option<&Item> {
+        self.item_index
+            .get(&(hex.to_owned(), map_item_index))
+            .map(|&i| &self.items[i])
+    }
+}
+
+impl Item {
+    /// Whichever of the nearest Major and Minor locations is closer.
+    pub fn nearest_place(&self) -> Option<&NearbyPlace> {
+        match (&self.nearest_major, &self.nearest_minor) {
+            (Some(major), Some(minor)) if minor.distance_m < major.distance_m => Some(minor),
+            (Some(major), _) => Some(major),
+            (None, minor) => minor.as_ref(),
+        }
+    }
+}
+
+fn nearest_place(places: &[Place], pos: WorldPos, major: bool) -> Option<NearbyPlace> {
+    places
+        .iter()
+        .filter(|p| p.major == major)
+        .map(|p| (p, p.pos.distance_to(&pos)))
+        .min_by_key(|(_, d)| ordered_float::OrderedFloat(*d))
+        .map(|(p, distance_m)| NearbyPlace {
+            name: p.name.clone(),
+            distance_m,
+            direction: p.pos.direction_to(&pos),
+        })
+}
+
+/// A small world for the tests: Deadlands and Callahan's Passage north of it, with a town
+/// each and a few things around them.
+#[cfg(test)]
+pub fn sample_maps() -> Vec<(String, Map)> {
+    use crate::warapi_schema::{MapItem, MapTextItem};
+
+    let place = |text: &str, major: bool, x: f32, y: f32| MapTextItem {
+        text: text.to_owned(),
+        x,
+        y,
+        map_marker_type: if major { "Major" } else { "Minor" }.to_owned(),
+    };
+    let item = |icon_type: i32, team_id: TeamId, flags: i32, x: f32, y: f32| MapItem {
+        team_id,
+        icon_type,
+        x,
+        y,
+        flags,
+    };
+    let map = |map_items, map_text_items| Map {
+        region_id: 0,
+        map_items,
+        map_text_items,
+        last_updated: 0,
+    };
+    vec![
+        (
+            "DeadLandsHex".to_owned(),
+            map(
+                vec![
+                    item(20, TeamId::Nobody, 0, 0.6, 0.6),
+                    item(38, TeamId::Wardens, 0, 0.5, 0.21),
+                    item(58, TeamId::Colonials, 0x01, 0.3, 0.7),
+                    item(11, TeamId::Colonials, 0, 0.32, 0.72),
+                    item(20, TeamId::Nobody, 0, 0.31, 0.6),
+                ],
+                vec![
+                    place("Brine Glen", true, 0.3, 0.7),
+                    place("The Spine", false, 0.5, 0.2),
+                ],
+            ),
+        ),
+        (
+            "CallahansPassageHex".to_owned(),
+            map(
+                vec![
+                    item(58, TeamId::Wardens, 0, 0.5, 0.5),
+                    item(61, TeamId::Nobody, 0, 0.2, 0.8),
+                    item(17, TeamId::Wardens, 0, 0.55, 0.5),
+                ],
+                vec![place("Scorpion", true, 0.5, 0.5)],
+            ),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        for (distance_m, expected) in [
+            (0.0, "0 m"),
+            (14.9, "10 m"),
+            (994.9, "990 m"),
+            (995.0, "1.0 km"),
+            (999.9, "1.0 km"),
+            (1049.0, "1.0 km"),
+            (1051.0, "1.1 km"),
+            (12345.0, "12.3 km"),
+        ] {
+            assert_eq!(format_distance(distance_m), expected, "{} m", distance_m);
+        }
+    }
+}